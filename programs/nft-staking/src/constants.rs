@@ -1,4 +1,62 @@
 pub const NFT_STAKE_MAX_COUNT: usize = 150;
+pub const MAX_REWARD_STREAMS: usize = 3;
 pub const DAY: i64 = 60 * 60 * 24; // 60 * 60 * 24; // 1 day
 pub const GLOBAL_AUTHORITY_SEED: &str = "global-authority";
 pub const VAULT_STAKE_SEED: &str = "vault-stake";
+pub const REWARD_VAULT_SEED: &str = "reward-vault";
+pub const REWARD_DEPOSIT_SEED: &str = "reward-deposit";
+pub const SOL_VAULT_SEED: &str = "sol-vault";
+pub const CAMPAIGN_SEED: &str = "campaign";
+pub const CAMPAIGN_VAULT_SEED: &str = "campaign-vault";
+
+// Layout version of GlobalPool, accounts created before versioning are version 0
+pub const GLOBAL_POOL_VERSION: u8 = 1;
+
+pub const ROLE_CONFIG_MANAGER: u8 = 0;
+pub const ROLE_TREASURER: u8 = 1;
+pub const ROLE_PAUSER: u8 = 2;
+pub const ROLE_TRAIT_ORACLE: u8 = 3;
+
+pub const ACCESS_OWNER: u8 = 0;
+pub const ACCESS_CLAIM: u8 = 1;
+pub const ACCESS_STAKE: u8 = 2;
+
+pub const PAUSE_STAKE: u8 = 1 << 0;
+pub const PAUSE_UNSTAKE: u8 = 1 << 1;
+pub const PAUSE_CLAIM: u8 = 1 << 2;
+pub const PAUSE_WITHDRAW: u8 = 1 << 3;
+// Pausing any staker operation stops accrual while exclude_paused_time is set
+pub const PAUSE_ACCRUAL: u8 = PAUSE_STAKE | PAUSE_UNSTAKE | PAUSE_CLAIM;
+pub const MAX_PAUSE_WINDOWS: usize = 8;
+
+pub const REWARD_SOURCE_VAULT: u8 = 0;
+pub const REWARD_SOURCE_MINT: u8 = 1;
+pub const REWARD_SOURCE_SOL: u8 = 2;
+
+pub const ELIGIBILITY_ALL: u8 = 0;
+pub const ELIGIBILITY_TRAIT: u8 = 1;
+pub const ELIGIBILITY_LOCK_TIER: u8 = 2;
+
+pub const TRANSFER_FEE_NET: u8 = 0;
+pub const TRANSFER_FEE_GROSS_UP: u8 = 1;
+
+pub const POST_LOCK_KEEP: u8 = 0;
+pub const POST_LOCK_NORMAL: u8 = 1;
+pub const POST_LOCK_CUSTOM: u8 = 2;
+
+pub const MAX_VESTING_TRANCHES: usize = 8;
+pub const MAX_BENEFICIARIES: usize = 4;
+pub const MAX_LOYALTY_TIERS: usize = 5;
+pub const MAX_HOLDING_TIERS: usize = 5;
+// Replaced bonus configs kept so wallets are credited under the config in force at the time
+pub const MAX_BONUS_HISTORY: usize = 4;
+pub const MAX_TRAITS: usize = 5;
+pub const MAX_CAMPAIGNS: usize = 4;
+// Campaigns hold one of the MAX_CAMPAIGNS slots, so they must be worth the slot and end
+// within a bounded time
+pub const MIN_CAMPAIGN_AMOUNT: u64 = 1_000_000;
+pub const MAX_CAMPAIGN_DURATION: i64 = 365 * DAY;
+// Time after a campaign ends during which unclaimed rewards stay reserved for stakers
+pub const CAMPAIGN_CLAIM_WINDOW: i64 = 30 * DAY;
+
+pub const BPS_DENOMINATOR: u64 = 10_000;
@@ -26,4 +26,80 @@ pub enum StakingError {
     MetadataCreatorParseError,
     #[msg("Invalid Metadata Address")]
     InvaliedMetadata,
+    #[msg("Account Is Already Migrated")]
+    AlreadyMigrated,
+    #[msg("Invalid Role")]
+    InvalidRole,
+    #[msg("Signer Doesn't Hold The Required Role")]
+    Unauthorized,
+    #[msg("This Operation Is Paused")]
+    OperationPaused,
+    #[msg("Emergency Unstake Is Not Enabled")]
+    EmergencyUnstakeDisabled,
+    #[msg("Invalid Reward Source")]
+    InvalidRewardSource,
+    #[msg("Global Authority Is Not The Reward Mint Authority")]
+    InvalidMintAuthority,
+    #[msg("Reward Mint Cap Exceeded")]
+    MintCapExceeded,
+    #[msg("Invalid Reward Stream")]
+    InvalidRewardStream,
+    #[msg("Too Many Reward Streams")]
+    RewardStreamOverflow,
+    #[msg("Reward Stream Accounts Mismatch")]
+    InvalidStreamAccounts,
+    #[msg("Invalid Campaign Parameters")]
+    InvalidCampaign,
+    #[msg("Campaign Has Not Ended")]
+    CampaignNotEnded,
+    #[msg("Invalid Token Program")]
+    InvalidTokenProgram,
+    #[msg("Invalid Token Account")]
+    InvalidTokenAccount,
+    #[msg("Invalid Transfer Fee Mode")]
+    InvalidTransferFeeMode,
+    #[msg("Invalid Vesting Schedule")]
+    InvalidVestingSchedule,
+    #[msg("Invalid Fee Configuration")]
+    InvalidFee,
+    #[msg("Invalid Treasury Account")]
+    InvalidTreasury,
+    #[msg("Invalid Beneficiary Configuration")]
+    InvalidBeneficiaries,
+    #[msg("Beneficiary Accounts Mismatch")]
+    InvalidBeneficiaryAccounts,
+    #[msg("Delegate Is Not Allowed To Do This")]
+    DelegateNotAllowed,
+    #[msg("Batch Accounts Mismatch")]
+    InvalidBatchAccounts,
+    #[msg("Invalid Lock Period")]
+    InvalidLockPeriod,
+    #[msg("Invalid Post Lock Mode")]
+    InvalidPostLockMode,
+    #[msg("Unbonding Is Not Enabled")]
+    UnbondingDisabled,
+    #[msg("Unstake Must Go Through Unbonding")]
+    UnbondingRequired,
+    #[msg("NFT Is Unbonding")]
+    Unbonding,
+    #[msg("Unbonding Period Has Not Ended")]
+    BeforeUnbondingEnd,
+    #[msg("Stake Is Still Warming Up")]
+    InWarmup,
+    #[msg("Invalid Loyalty Tiers")]
+    InvalidLoyaltyTiers,
+    #[msg("Invalid Holding Tiers")]
+    InvalidHoldingTiers,
+    #[msg("Invalid Trait Set")]
+    InvalidTraitSet,
+    #[msg("Too Many Running Campaigns")]
+    CampaignOverflow,
+    #[msg("Invalid Unbonding Period")]
+    InvalidUnbondingPeriod,
+    #[msg("Invalid Warmup Period")]
+    InvalidWarmupPeriod,
+    #[msg("User Pool Still Holds NFTs Or Rewards")]
+    PoolNotEmpty,
+    #[msg("Unknown Trait")]
+    UnknownTrait,
 }
@@ -0,0 +1,9 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct RewardBurned {
+    pub global_authority: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub total_burned: u64,
+}
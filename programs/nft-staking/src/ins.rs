@@ -1,20 +1,22 @@
 use anchor_lang::prelude::*;
 use anchor_spl::{
-  token::{Token, TokenAccount},
+  token::{Mint, Token, TokenAccount},
 };
 
 use crate::state::*;
 use crate::constants::*;
+use crate::errors::*;
+use crate::token_interface::is_token_program;
 
 
 #[derive(Accounts)]
-#[instruction(global_bump: u8, global_name: String)]
+#[instruction(global_bump: u8, global_name: String, nft_creator: Pubkey, reward_token_mint: Pubkey)]
 pub struct InitializeGlobal<'info> {
     #[account(mut)]
     pub admin: Signer<'info>,
 
     #[account(
-        init_if_needed,
+        init,
         seeds = [
           global_name.as_ref(),
           GLOBAL_AUTHORITY_SEED.as_ref(),
@@ -25,10 +27,49 @@ pub struct InitializeGlobal<'info> {
     )]
     pub global_authority: Account<'info, GlobalPool>,
 
+    /// CHECK:
+    #[account(constraint = reward_mint.key() == reward_token_mint)]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// CHECK: created in the handler if needed
+    #[account(
+        mut,
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          reward_mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
     pub system_program: Program<'info, System>,
+    /// CHECK: spl-token or Token-2022
+    #[account(constraint = is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
     pub rent: Sysvar<'info, Rent>,
 }
 
+#[derive(Accounts)]
+#[instruction(global_bump: u8, global_name: String)]
+pub struct MigrateGlobal<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    /// CHECK: an older GlobalPool layout, checked and upgraded in the handler
+    #[account(
+        mut,
+        seeds = [
+          global_name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(global_bump: u8)]
 pub struct UpdateAdmin<'info> {
@@ -63,6 +104,57 @@ pub struct UpdateGlobal<'info> {
     pub global_authority: Account<'info, GlobalPool>,
 }
 
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct UpdateRole<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct UpdateTraits<'info> {
+    #[account(mut)]
+    pub trait_oracle: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct UpdatePause<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
 #[derive(Accounts)]
 pub struct InitializeFixedPool<'info> {
     #[account(zero)]
@@ -72,6 +164,29 @@ pub struct InitializeFixedPool<'info> {
     pub owner: Signer<'info>,
 }
 
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct MigrateUserPool<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: a UserPool in the version 0 layout, checked and upgraded in the handler
+    #[account(mut)]
+    pub user_fixed_pool: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(global_bump: u8)]
 pub struct StakeNftToFixed<'info> {
@@ -121,6 +236,43 @@ pub struct StakeNftToFixed<'info> {
     /// CHECK:
     #[account(constraint = token_metadata_program.key == &metaplex_token_metadata::ID)]
     pub token_metadata_program: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when an action fee is charged
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct StakeNftsToFixed<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    pub token_program: Program<'info, Token>,
+
+    // the token metadata program
+    /// CHECK:
+    #[account(constraint = token_metadata_program.key == &metaplex_token_metadata::ID)]
+    pub token_metadata_program: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when an action fee is charged
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
@@ -171,11 +323,16 @@ pub struct WithdrawNftFromFixed<'info> {
     /// CHECK:
     #[account(constraint = token_metadata_program.key == &metaplex_token_metadata::ID)]
     pub token_metadata_program: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when an action fee is charged
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(global_bump: u8)]
-pub struct ClaimRewardAll<'info> {
+#[instruction(global_bump: u8, vault_stake_bump: u8)]
+pub struct CompleteUnstake<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
 
@@ -192,26 +349,40 @@ pub struct ClaimRewardAll<'info> {
     )]
     pub global_authority: Account<'info, GlobalPool>,
 
+    /// CHECK:
     #[account(
         mut,
-        constraint = reward_vault.mint == global_authority.reward_token_mint,
-        constraint = reward_vault.owner == global_authority.key(),
+        seeds = [
+            VAULT_STAKE_SEED.as_bytes(), 
+            global_authority.key().as_ref(), 
+            owner.key().as_ref(),
+            user_token_account.key().as_ref(),
+        ],
+        bump = vault_stake_bump,
     )]
-    pub reward_vault: Box<Account<'info, TokenAccount>>,
+    pub vault_pda: AccountInfo<'info>,
+    /// CHECK:
+    pub edition: AccountInfo<'info>,
 
     #[account(
         mut,
-        constraint = user_reward_account.mint == global_authority.reward_token_mint,
-        constraint = user_reward_account.owner == owner.key(),
+        constraint = user_token_account.mint == *nft_mint.to_account_info().key,
+        // constraint = user_token_account.owner == *owner.to_account_info().key,
     )]
-    pub user_reward_account: Account<'info, TokenAccount>,
-
+    pub user_token_account: Account<'info, TokenAccount>,
+    
+    /// CHECK:
+    pub nft_mint: AccountInfo<'info>,
     pub token_program: Program<'info, Token>,
+    // the token metadata program
+    /// CHECK:
+    #[account(constraint = token_metadata_program.key == &metaplex_token_metadata::ID)]
+    pub token_metadata_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
 #[instruction(global_bump: u8)]
-pub struct ClaimReward<'info> {
+pub struct WithdrawNftsFromFixed<'info> {
     #[account(mut)]
     pub owner: Signer<'info>,
 
@@ -228,32 +399,92 @@ pub struct ClaimReward<'info> {
     )]
     pub global_authority: Account<'info, GlobalPool>,
 
+    pub token_program: Program<'info, Token>,
+    // the token metadata program
+    /// CHECK:
+    #[account(constraint = token_metadata_program.key == &metaplex_token_metadata::ID)]
+    pub token_metadata_program: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when an action fee is charged
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetEmergencyUnstake<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
     #[account(
         mut,
-        constraint = reward_vault.mint == global_authority.reward_token_mint,
-        constraint = reward_vault.owner == global_authority.key(),
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
     )]
-    pub reward_vault: Box<Account<'info, TokenAccount>>,
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8, vault_stake_bump: u8)]
+pub struct EmergencyUnstake<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
 
     #[account(
         mut,
-        constraint = user_reward_account.mint == global_authority.reward_token_mint,
-        constraint = user_reward_account.owner == owner.key(),
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
     )]
-    pub user_reward_account: Account<'info, TokenAccount>,
+    pub global_authority: Account<'info, GlobalPool>,
 
     /// CHECK:
-    pub nft_mint: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [
+            VAULT_STAKE_SEED.as_bytes(), 
+            global_authority.key().as_ref(), 
+            owner.key().as_ref(),
+            user_token_account.key().as_ref(),
+        ],
+        bump = vault_stake_bump,
+    )]
+    pub vault_pda: AccountInfo<'info>,
+    /// CHECK:
+    pub edition: AccountInfo<'info>,
 
+    #[account(
+        mut,
+        constraint = user_token_account.mint == *nft_mint.to_account_info().key,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    
+    /// CHECK:
+    pub nft_mint: AccountInfo<'info>,
     pub token_program: Program<'info, Token>,
+    // the token metadata program
+    /// CHECK:
+    #[account(constraint = token_metadata_program.key == &metaplex_token_metadata::ID)]
+    pub token_metadata_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
 #[instruction(global_bump: u8)]
-pub struct WithdrawReward<'info>
-{
+pub struct ClaimRewardAll<'info> {
     #[account(mut)]
-    pub claimer: Signer<'info>,
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
 
     #[account(
         mut,
@@ -265,27 +496,1002 @@ pub struct WithdrawReward<'info>
     )]
     pub global_authority: Account<'info, GlobalPool>,
 
+    /// CHECK:
     #[account(
         mut,
-        constraint = reward_vault.mint == global_authority.reward_token_mint,
-        constraint = reward_vault.owner == global_authority.key(),
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          global_authority.reward_token_mint.as_ref(),
+        ],
+        bump,
     )]
-    pub reward_vault: Box<Account<'info, TokenAccount>>,
+    pub reward_vault: AccountInfo<'info>,
 
+    /// CHECK:
     #[account(
         mut,
-        constraint = claimer_reward_account.mint == global_authority.reward_token_mint,
-        constraint = claimer_reward_account.owner == claimer.key(),
+        constraint = reward_mint.key() == global_authority.reward_token_mint,
     )]
-    pub claimer_reward_account: Account<'info, TokenAccount>,
+    pub reward_mint: AccountInfo<'info>,
 
-    pub token_program: Program<'info, Token>,
+    /// CHECK: mint and owner are checked in the handler when the owner keeps a share
+    #[account(mut)]
+    pub user_reward_account: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when a claim fee is charged
+    #[account(mut)]
+    pub treasury_reward_account: AccountInfo<'info>,
+
+    /// CHECK: spl-token or Token-2022
+    #[account(constraint = is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
-pub struct CloseUserFixedPool<'info> {
+pub struct SetDelegate<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+}
+
+#[derive(Accounts)]
+pub struct SetBeneficiaries<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ReleaseVested<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
     #[account(mut)]
-    pub owner: SystemAccount<'info>,
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    #[account(
+        mut,
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          global_authority.reward_token_mint.as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
+    /// CHECK:
+    #[account(
+        mut,
+        constraint = reward_mint.key() == global_authority.reward_token_mint,
+    )]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// CHECK: mint and owner are checked in the handler when the owner keeps a share
+    #[account(mut)]
+    pub user_reward_account: AccountInfo<'info>,
+
+    /// CHECK: spl-token or Token-2022
+    #[account(constraint = is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ClaimReward<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    #[account(
+        mut,
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          global_authority.reward_token_mint.as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
+    /// CHECK:
+    #[account(
+        mut,
+        constraint = reward_mint.key() == global_authority.reward_token_mint,
+    )]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// CHECK: mint and owner are checked in the handler when the owner keeps a share
+    #[account(mut)]
+    pub user_reward_account: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when a claim fee is charged
+    #[account(mut)]
+    pub treasury_reward_account: AccountInfo<'info>,
+
+    /// CHECK:
+    pub nft_mint: AccountInfo<'info>,
+
+    /// CHECK: spl-token or Token-2022
+    #[account(constraint = is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetPartialPayouts<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ExtendLock<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    pub nft_mint: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct RequestUnstake<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    pub nft_mint: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when an action fee is charged
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetAutoRelock<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    pub nft_mint: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetEarlyUnstake<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetPostLockRate<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetUnbondingPeriod<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetWarmupPeriod<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetLoyaltyTiers<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetHoldingTiers<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetTraitSetBonus<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetVesting<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetFees<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetBurnRate<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetTransferFeeMode<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct VaultRunway<'info> {
+    #[account(
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    #[account(
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          global_authority.reward_token_mint.as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
+    /// CHECK:
+    #[account(constraint = reward_mint.key() == global_authority.reward_token_mint)]
+    pub reward_mint: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct SetRewardSource<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    #[account(constraint = reward_mint.key() == global_authority.reward_token_mint)]
+    pub reward_mint: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct AddRewardStream<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    pub stream_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init_if_needed,
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          stream_mint.key().as_ref(),
+        ],
+        bump,
+        payer = config_manager,
+        token::mint = stream_mint,
+        token::authority = global_authority,
+    )]
+    pub stream_vault: Box<Account<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct UpdateRewardStream<'info> {
+    #[account(mut)]
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ClaimSolRewardAll<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          SOL_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    /// CHECK: must be the pool owner, checked in the handler
+    #[account(mut)]
+    pub pool_owner: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when a claim fee is charged
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ClaimSolReward<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          SOL_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    /// CHECK:
+    pub nft_mint: AccountInfo<'info>,
+
+    /// CHECK: must be the pool owner, checked in the handler
+    #[account(mut)]
+    pub pool_owner: AccountInfo<'info>,
+
+    /// CHECK: checked in the handler when a claim fee is charged
+    #[account(mut)]
+    pub treasury: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct FundSolRewards<'info> {
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          SOL_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub sol_vault: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ClaimStreamReward<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    #[account(
+        mut,
+        constraint = stream_vault.owner == global_authority.key(),
+    )]
+    pub stream_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_stream_account.mint == stream_vault.mint,
+    )]
+    pub user_stream_account: Account<'info, TokenAccount>,
+
+    /// CHECK: checked in the handler when a claim fee is charged
+    #[account(mut)]
+    pub treasury_stream_account: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8, params: CampaignParams)]
+pub struct CreateCampaign<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        init,
+        seeds = [
+          CAMPAIGN_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          sponsor.key().as_ref(),
+          params.campaign_id.to_le_bytes().as_ref(),
+        ],
+        bump,
+        payer = sponsor,
+        space = Campaign::LEN + 8
+    )]
+    pub campaign: Box<Account<'info, Campaign>>,
+
+    pub campaign_mint: Box<Account<'info, Mint>>,
+
+    #[account(
+        init,
+        seeds = [
+          CAMPAIGN_VAULT_SEED.as_ref(),
+          campaign.key().as_ref(),
+        ],
+        bump,
+        payer = sponsor,
+        token::mint = campaign_mint,
+        token::authority = global_authority,
+    )]
+    pub campaign_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = sponsor_token_account.mint == campaign_mint.key(),
+        constraint = sponsor_token_account.owner == sponsor.key(),
+    )]
+    pub sponsor_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ClaimCampaignReward<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub user_fixed_pool: AccountLoader<'info, UserPool>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        constraint = campaign.global_authority == global_authority.key(),
+    )]
+    pub campaign: Box<Account<'info, Campaign>>,
+
+    #[account(
+        mut,
+        seeds = [
+          CAMPAIGN_VAULT_SEED.as_ref(),
+          campaign.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub campaign_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = user_campaign_account.mint == campaign.mint,
+    )]
+    pub user_campaign_account: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: checked in the handler when a claim fee is charged
+    #[account(mut)]
+    pub treasury_campaign_account: AccountInfo<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct CloseCampaign<'info> {
+    pub config_manager: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        mut,
+        constraint = campaign.global_authority == global_authority.key(),
+    )]
+    pub campaign: Box<Account<'info, Campaign>>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct ReclaimCampaign<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    #[account(
+        constraint = campaign.global_authority == global_authority.key(),
+        constraint = campaign.sponsor == sponsor.key(),
+    )]
+    pub campaign: Box<Account<'info, Campaign>>,
+
+    #[account(
+        mut,
+        seeds = [
+          CAMPAIGN_VAULT_SEED.as_ref(),
+          campaign.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub campaign_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        constraint = sponsor_token_account.mint == campaign.mint,
+        constraint = sponsor_token_account.owner == sponsor.key(),
+    )]
+    pub sponsor_token_account: Box<Account<'info, TokenAccount>>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct FundRewards<'info> {
+    #[account(mut)]
+    pub funder: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    /// CHECK:
+    #[account(constraint = global_authority.is_reward_mint(&reward_mint.key()))]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// CHECK: created in the handler if needed
+    #[account(
+        mut,
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          reward_mint.key().as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
+    /// CHECK: mint and owner are checked in the handler
+    #[account(mut)]
+    pub funder_token_account: AccountInfo<'info>,
+
+    #[account(
+        init_if_needed,
+        seeds = [
+          REWARD_DEPOSIT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          reward_mint.key().as_ref(),
+          funder.key().as_ref(),
+        ],
+        bump,
+        payer = funder,
+        space = RewardDeposit::LEN + 8
+    )]
+    pub reward_deposit: Account<'info, RewardDeposit>,
+
+    pub system_program: Program<'info, System>,
+    /// CHECK: spl-token or Token-2022
+    #[account(constraint = is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct MigrateRewardVault<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Box<Account<'info, GlobalPool>>,
+
+    /// CHECK: mint and owner are checked in the handler
+    #[account(mut, constraint = legacy_vault.key() != reward_vault.key())]
+    pub legacy_vault: AccountInfo<'info>,
+
+    /// CHECK: created in the handler if needed
+    #[account(
+        mut,
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          global_authority.reward_token_mint.as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
+    /// CHECK:
+    #[account(constraint = reward_mint.key() == global_authority.reward_token_mint)]
+    pub reward_mint: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+    /// CHECK: spl-token or Token-2022
+    #[account(constraint = is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(global_bump: u8)]
+pub struct WithdrawReward<'info>
+{
+    #[account(mut)]
+    pub claimer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [
+          global_authority.name.as_ref(),
+          GLOBAL_AUTHORITY_SEED.as_ref()
+        ],
+        bump = global_bump,
+    )]
+    pub global_authority: Account<'info, GlobalPool>,
+
+    /// CHECK:
+    #[account(
+        mut,
+        seeds = [
+          REWARD_VAULT_SEED.as_ref(),
+          global_authority.key().as_ref(),
+          global_authority.reward_token_mint.as_ref(),
+        ],
+        bump,
+    )]
+    pub reward_vault: AccountInfo<'info>,
+
+    /// CHECK:
+    #[account(constraint = reward_mint.key() == global_authority.reward_token_mint)]
+    pub reward_mint: AccountInfo<'info>,
+
+    /// CHECK: mint and owner are checked in the handler
+    #[account(mut)]
+    pub claimer_reward_account: AccountInfo<'info>,
+
+    /// CHECK: spl-token or Token-2022
+    #[account(constraint = is_token_program(token_program.key) @ StakingError::InvalidTokenProgram)]
+    pub token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseUserFixedPool<'info> {
+    #[account(mut)]
+    pub owner: Signer<'info>,
 
     #[account(mut)]
     pub user_fixed_pool: AccountLoader<'info, UserPool>,
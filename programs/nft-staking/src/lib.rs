@@ -2,19 +2,26 @@ mod state;
 mod ins;
 mod constants;
 mod errors;
+mod migration;
+mod events;
+mod token_interface;
 
 use anchor_lang::prelude::*;
 use metaplex_token_metadata::state::Metadata;
 use spl_token::instruction::AuthorityType::AccountOwner;
 use anchor_spl::{
-    token::{self, Transfer},
+    token::{self, TokenAccount, Transfer},
 };
-use solana_program::program::invoke_signed;
+use solana_program::program_option::COption;
+use solana_program::program::{invoke, invoke_signed};
+use solana_program::system_instruction;
 use mpl_token_metadata::instruction::{freeze_delegated_account, thaw_delegated_account};
 
 use ins::*;
 use constants::*;
 use errors::*;
+use migration::*;
+use events::*;
 use state::*;
 
 declare_id!("Gfoam73aJ33wjPk4cLBnE6JQZ62twXjuoQokqp7imBMr");
@@ -23,6 +30,7 @@ declare_id!("Gfoam73aJ33wjPk4cLBnE6JQZ62twXjuoQokqp7imBMr");
 pub mod nft_staking {
     use super::*;
 
+    #[allow(clippy::too_many_arguments)]
     pub fn initialize_global(
         ctx: Context<InitializeGlobal>, 
         _global_bump: u8, 
@@ -48,6 +56,51 @@ pub mod nft_staking {
         global_authority.lock_durations = lock_durations;
         global_authority.lock_rates = lock_rates;
         global_authority.custodial = custodial;
+        global_authority.reward_source = REWARD_SOURCE_VAULT;
+        global_authority.transfer_fee_mode = TRANSFER_FEE_NET;
+        global_authority.version = GLOBAL_POOL_VERSION;
+
+        let global_key = global_authority.key();
+        let mint_key = ctx.accounts.reward_mint.key();
+        let vault_bump = *ctx.bumps.get("reward_vault").unwrap();
+        token_interface::create_token_account(
+            &ctx.accounts.admin.to_account_info(),
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+            &global_key,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program.to_account_info(),
+            &[REWARD_VAULT_SEED.as_bytes(), global_key.as_ref(), mint_key.as_ref(), &[vault_bump]],
+        )?;
+        Ok(())
+    }
+
+    /// Upgrades a GlobalPool created by an older program version to the current layout,
+    /// growing the account and initializing the new fields. Only the admin can migrate.
+    pub fn migrate_global(ctx: Context<MigrateGlobal>, _global_bump: u8, _global_name: String) -> Result<()> {
+        let global_info = ctx.accounts.global_authority.to_account_info();
+        require!(global_info.owner.eq(&crate::ID), StakingError::InvalidPoolError);
+        let old = {
+            let data = global_info.try_borrow_data()?;
+            require!(data.len() >= 8 && data[..8] == <GlobalPool as anchor_lang::Discriminator>::discriminator(), StakingError::InvalidPoolError);
+            if data.len() != 8 + GlobalPoolV0::LEN {
+                let global_pool = GlobalPool::try_deserialize(&mut &data[..])?;
+                require!(global_pool.version < GLOBAL_POOL_VERSION, StakingError::AlreadyMigrated);
+            }
+            GlobalPoolV0::deserialize(&mut &data[8..])?
+        };
+        require!(ctx.accounts.admin.key() == old.admin, StakingError::InvalidAdmin);
+
+        let global_pool = GlobalPool::from_v0(old);
+        grow_account(
+            &global_info,
+            &ctx.accounts.admin.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            GlobalPool::LEN + 8,
+        )?;
+        let mut data = global_info.try_borrow_mut_data()?;
+        global_pool.try_serialize(&mut &mut data[..])?;
+        msg!("Migrated to version {}", GLOBAL_POOL_VERSION);
         Ok(())
     }
 
@@ -66,6 +119,34 @@ pub mod nft_staking {
         Ok(())
     }
 
+    pub fn set_role(
+        ctx: Context<UpdateRole>,
+        _global_bump: u8,
+        role: u8,
+        holder: Pubkey,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        require!(
+            ctx.accounts.admin.key() == global_authority.admin,
+            StakingError::InvalidAdmin
+        );
+        global_authority.set_role(role, holder)?;
+
+        Ok(())
+    }
+
+    pub fn revoke_role(ctx: Context<UpdateRole>, _global_bump: u8, role: u8) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        require!(
+            ctx.accounts.admin.key() == global_authority.admin,
+            StakingError::InvalidAdmin
+        );
+        global_authority.set_role(role, Pubkey::default())?;
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn update_global(
         ctx: Context<UpdateGlobal>, 
         _global_bump: u8, 
@@ -79,10 +160,7 @@ pub mod nft_staking {
         custodial: bool,
     ) -> Result<()> {
         let global_authority = &mut ctx.accounts.global_authority;
-        require!(
-            ctx.accounts.admin.key() == global_authority.admin,
-            StakingError::InvalidAdmin
-        );
+        role(global_authority, &ctx.accounts.admin, ROLE_CONFIG_MANAGER)?;
         global_authority.nft_creator = nft_creator;
         global_authority.reward_token_mint = reward_token_mint;
         global_authority.trait_rates = trait_rates;
@@ -94,13 +172,96 @@ pub mod nft_staking {
         Ok(())
     }
 
+    pub fn update_traits(
+        ctx: Context<UpdateTraits>,
+        _global_bump: u8,
+        trait_rates: Vec<u64>,
+        trait_names: Vec<String>,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.trait_oracle, ROLE_TRAIT_ORACLE)?;
+        global_authority.trait_rates = trait_rates;
+        global_authority.trait_names = trait_names;
+        Ok(())
+    }
+
+    pub fn pause(ctx: Context<UpdatePause>, _global_bump: u8, flags: u8) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.authority, ROLE_PAUSER)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        global_authority.pause(flags, timestamp);
+        Ok(())
+    }
+
+    pub fn unpause(ctx: Context<UpdatePause>, _global_bump: u8, flags: u8) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        require!(
+            ctx.accounts.authority.key() == global_authority.admin,
+            StakingError::InvalidAdmin
+        );
+        let timestamp = Clock::get()?.unix_timestamp;
+        global_authority.unpause(flags, timestamp);
+        Ok(())
+    }
+
+    pub fn update_pause_config(
+        ctx: Context<UpdatePause>,
+        _global_bump: u8,
+        exclude_paused_time: bool,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        require!(
+            ctx.accounts.authority.key() == global_authority.admin,
+            StakingError::InvalidAdmin
+        );
+        global_authority.exclude_paused_time = exclude_paused_time;
+        Ok(())
+    }
+
     pub fn initialize_fixed_pool(ctx: Context<InitializeFixedPool>) -> Result<()> {
         let mut fixed_pool = ctx.accounts.user_fixed_pool.load_init()?;
         fixed_pool.owner = ctx.accounts.owner.key();
         Ok(())
     }
 
-    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner))]
+    /// Upgrades a user pool created by an older program version to the current layout. Run
+    /// migrate_global first, the pool's NFTs are added back into the global totals here.
+    pub fn migrate_user_pool(ctx: Context<MigrateUserPool>, _global_bump: u8) -> Result<()> {
+        let pool_info = ctx.accounts.user_fixed_pool.to_account_info();
+        require!(pool_info.owner.eq(&crate::ID), StakingError::InvalidPoolError);
+        let old = {
+            let data = pool_info.try_borrow_data()?;
+            require!(data.len() >= 8 && data[..8] == <UserPool as anchor_lang::Discriminator>::discriminator(), StakingError::InvalidPoolError);
+            require!(data.len() == 8 + UserPoolV0::LEN, StakingError::AlreadyMigrated);
+            UserPoolV0::read(&data[8..])?
+        };
+        require!(ctx.accounts.owner.key() == old.owner, StakingError::InvalidOwner);
+
+        grow_account(
+            &pool_info,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            std::mem::size_of::<UserPool>() + 8,
+        )?;
+        pool_info.try_borrow_mut_data()?[8..].fill(0);
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.accrue_liability(timestamp);
+        global_authority.advance_campaigns(timestamp);
+        let user_fixed_pool = AccountLoader::<UserPool>::try_from(&pool_info)?;
+        let mut user_pool = user_fixed_pool.load_mut()?;
+        let owed = user_pool.migrate(&old, global_authority, timestamp);
+        for item in user_pool.items[..user_pool.item_count as usize].iter() {
+            global_authority.emission_rate += item.rate as u64;
+            global_authority.count_stake(item, true);
+        }
+        global_authority.total_accrued += owed;
+        msg!("Migrated {} staked NFTs", user_pool.item_count);
+        Ok(())
+    }
+
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_STAKE))]
     pub fn stake_nft_to_fixed(
         ctx: Context<StakeNftToFixed>,
         _global_bump: u8,
@@ -108,158 +269,229 @@ pub mod nft_staking {
         role: String,
         model: u64,
     ) -> Result<()> {
-        let mint_metadata = &mut &ctx.accounts.mint_metadata;
-
-        msg!("Metadata Account: {:?}", ctx.accounts.mint_metadata.key());
-        let (metadata, _) = Pubkey::find_program_address(
-            &[
-                metaplex_token_metadata::state::PREFIX.as_bytes(),
-                metaplex_token_metadata::id().as_ref(),
-                ctx.accounts.nft_mint.key().as_ref(),
-            ],
-            &metaplex_token_metadata::id(),
-        );
+        ctx.accounts.global_authority.check_not_paused(PAUSE_STAKE)?;
+        // A delegate stakes from its own wallet, which only works when the vault takes custody
+        let pool_owner = ctx.accounts.user_fixed_pool.load()?.owner;
         require!(
-            metadata == mint_metadata.key(),
-            StakingError::InvaliedMetadata
+            pool_owner == ctx.accounts.owner.key() || ctx.accounts.global_authority.custodial,
+            StakingError::DelegateNotAllowed
         );
-
-        let global_authority = &mut ctx.accounts.global_authority;
-
-        // verify metadata is legit
-        let nft_metadata = Metadata::from_account_info(mint_metadata)?;
-
-        if let Some(creators) = nft_metadata.data.creators {
-            let mut valid: u8 = 0;
-            for creator in creators {
-                if creator.address == global_authority.nft_creator && creator.verified == true
-                {
-                    valid = 1;
-                    break;
-                }
-            }
-            if valid != 1 {
-                return Err(StakingError::InvalidCollection.into());
-            }
-        } else {
-            return Err(StakingError::MetadataCreatorParseError.into());
-        };
-
+        charge_action_fee(
+            &mut ctx.accounts.global_authority,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program.to_account_info(),
+            1,
+        )?;
+        msg!("Metadata Account: {:?}", ctx.accounts.mint_metadata.key());
+        verify_nft_metadata(
+            &ctx.accounts.global_authority,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.mint_metadata,
+        )?;
 
         let timestamp = Clock::get()?.unix_timestamp;
-        let lock_time = timestamp + DAY * lock_period as i64;
-
-        let mut rate: i64 = 0;
-        if model == 1 {
-            let index = global_authority.trait_names.iter().position(|x| x == role.as_str());
-            if let Some(index) = index {
-                rate = global_authority.trait_rates[index] as i64;
-            }
-        }
-        if model == 2 {
-            rate = global_authority.normal_rate as i64;
-        }
-        if model == 3 {
-            let index = global_authority.lock_durations.iter().position(|x| *x == lock_period);
-            if let Some(index) = index {
-                rate = global_authority.lock_rates[index] as i64
-            }
-        }
-        let staked_item = StakedNFT {
-            nft_addr: ctx.accounts.nft_mint.key(),
-            stake_time: timestamp,
-            reward_time: timestamp,
-            lock_time: lock_time,
-            rate: rate,
-            model: model,
-        };
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
+        let staked_item = new_staked_item(
+            &ctx.accounts.global_authority,
+            ctx.accounts.nft_mint.key(),
+            lock_period,
+            &role,
+            model,
+            timestamp,
+        )?;
         let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        fixed_pool.checkpoint_bonuses(&ctx.accounts.global_authority, timestamp);
         fixed_pool.add_nft(staked_item);
 
         ctx.accounts.global_authority.total_amount += 1;
-        let token_account_info = &mut &ctx.accounts.user_token_account;
-        
+        ctx.accounts.global_authority.count_stake(&staked_item, true);
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        ctx.accounts.global_authority.emission_rate += staked_item.rate as u64;
+
+        let global_authority = ctx.accounts.global_authority.key();
+        let token_account_info = ctx.accounts.user_token_account.key();
         let (vault_pda, vault_stake_bump) = Pubkey::find_program_address(
             &[
                 VAULT_STAKE_SEED.as_bytes(),
-                ctx.accounts.global_authority.key().as_ref(),
-                ctx.accounts.owner.key().as_ref(),
-                token_account_info.key().as_ref()
+                global_authority.as_ref(),
+                pool_owner.as_ref(),
+                token_account_info.as_ref()
             ],
             ctx.program_id
         );
-
-        let global_authority = ctx.accounts.global_authority.key().clone();
-        let owner = ctx.accounts.owner.key().clone();
-        let token_account_info = ctx.accounts.user_token_account.key().clone();
-
         let seeds = &[
             VAULT_STAKE_SEED.as_bytes(),
             global_authority.as_ref(),
-            owner.as_ref(),
+            pool_owner.as_ref(),
             token_account_info.as_ref(),
             &[vault_stake_bump],
         ];
 
-        if ctx.accounts.global_authority.custodial == false {
-            let cpi_context = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                anchor_spl::token::Approve {
-                    to: ctx.accounts.user_token_account.to_account_info().clone(),
-                    delegate: ctx.accounts.vault_pda.to_account_info(),
-                    authority: ctx.accounts.owner.to_account_info()
-                }
+        lock_nft(
+            ctx.accounts.global_authority.custodial,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.vault_pda,
+            vault_pda,
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.edition,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.token_metadata_program,
+            seeds,
+        )?;
+
+        Ok(())
+    }
+
+    /// Stakes several NFTs at once. Remaining accounts hold one
+    /// (token account, mint, metadata, edition, vault PDA) group per NFT and roles[i]
+    /// names the trait of the i-th NFT for model 1.
+    /// Each NFT costs roughly 45k compute units non-custodial (metadata parse, vault PDA
+    /// search, approve and freeze) and 30k custodial, so batches beyond four or five NFTs
+    /// need a higher compute unit limit and an address lookup table for the accounts.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_STAKE))]
+    pub fn stake_nfts_to_fixed<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, StakeNftsToFixed<'info>>,
+        _global_bump: u8,
+        lock_period: u8,
+        roles: Vec<String>,
+        model: u64,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_STAKE)?;
+        let pool_owner = ctx.accounts.user_fixed_pool.load()?.owner;
+        require!(
+            pool_owner == ctx.accounts.owner.key() || ctx.accounts.global_authority.custodial,
+            StakingError::DelegateNotAllowed
+        );
+        let count = ctx.remaining_accounts.len() / 5;
+        require!(
+            count * 5 == ctx.remaining_accounts.len() && (model != 1 || roles.len() == count),
+            StakingError::InvalidBatchAccounts
+        );
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        require!(
+            fixed_pool.item_count as usize + count <= NFT_STAKE_MAX_COUNT,
+            StakingError::IndexOverflow
+        );
+        charge_action_fee(
+            &mut ctx.accounts.global_authority,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program.to_account_info(),
+            count as u64,
+        )?;
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
+        fixed_pool.checkpoint_bonuses(&ctx.accounts.global_authority, timestamp);
+        let global_authority = ctx.accounts.global_authority.key();
+        for (index, group) in ctx.remaining_accounts.chunks(5).enumerate() {
+            let user_token_account = &group[0];
+            let nft_mint = &group[1];
+            let mint_metadata = &group[2];
+            let edition = &group[3];
+            let vault_pda_info = &group[4];
+
+            let token_account = Account::<TokenAccount>::try_from(user_token_account)?;
+            require!(
+                token_account.mint == nft_mint.key()
+                    && token_account.owner == ctx.accounts.owner.key()
+                    && token_account.amount == 1,
+                StakingError::InvalidTokenAccount
+            );
+            require!(
+                mint_metadata.owner == &metaplex_token_metadata::ID,
+                StakingError::InvaliedMetadata
             );
-    
-            anchor_spl::token::approve(cpi_context, 1)?;
-            invoke_signed(
-                &freeze_delegated_account(
-                    ctx.accounts.token_metadata_program.key(),
-                    ctx.accounts.vault_pda.key(),
-                    token_account_info.key(),
-                    ctx.accounts.edition.key(),
-                    ctx.accounts.nft_mint.key(),
-                ),
+            verify_nft_metadata(&ctx.accounts.global_authority, nft_mint, mint_metadata)?;
+
+            let role = roles.get(index).map(|role| role.as_str()).unwrap_or_default();
+            let staked_item = new_staked_item(
+                &ctx.accounts.global_authority,
+                nft_mint.key(),
+                lock_period,
+                role,
+                model,
+                timestamp,
+            )?;
+            fixed_pool.add_nft(staked_item);
+            ctx.accounts.global_authority.total_amount += 1;
+            ctx.accounts.global_authority.count_stake(&staked_item, true);
+            ctx.accounts.global_authority.emission_rate += staked_item.rate as u64;
+
+            let token_account_key = user_token_account.key();
+            let (vault_pda, vault_stake_bump) = Pubkey::find_program_address(
                 &[
-                    ctx.accounts.vault_pda.to_account_info(),
-                    ctx.accounts.user_token_account.to_account_info(),
-                    ctx.accounts.edition.to_account_info(),
-                    ctx.accounts.nft_mint.to_account_info()
+                    VAULT_STAKE_SEED.as_bytes(),
+                    global_authority.as_ref(),
+                    pool_owner.as_ref(),
+                    token_account_key.as_ref()
                 ],
-                &[seeds]
-            )?;
-        } else {
-            let cpi_context = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                anchor_spl::token::SetAuthority {
-                    current_authority: ctx.accounts.owner.to_account_info().clone(),
-                    account_or_mint: ctx.accounts.user_token_account.to_account_info().clone(),
-                },
+                ctx.program_id
             );
-            
-            anchor_spl::token::set_authority(cpi_context, AccountOwner, Some(vault_pda))?;
+            let seeds = &[
+                VAULT_STAKE_SEED.as_bytes(),
+                global_authority.as_ref(),
+                pool_owner.as_ref(),
+                token_account_key.as_ref(),
+                &[vault_stake_bump],
+            ];
+            lock_nft(
+                ctx.accounts.global_authority.custodial,
+                &ctx.accounts.owner.to_account_info(),
+                vault_pda_info,
+                vault_pda,
+                user_token_account,
+                edition,
+                nft_mint,
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.token_metadata_program,
+                seeds,
+            )?;
         }
-        
+        msg!("Staked: {}", count);
+
         Ok(())
     }
 
-    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner))]
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_OWNER))]
     pub fn withdraw_nft_from_fixed(
         ctx: Context<WithdrawNftFromFixed>,
         _global_bump: u8,
         vault_stake_bump: u8,
     ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_UNSTAKE)?;
+        require!(
+            ctx.accounts.global_authority.unbonding_period == 0,
+            StakingError::UnbondingRequired
+        );
+        charge_action_fee(
+            &mut ctx.accounts.global_authority,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program.to_account_info(),
+            1,
+        )?;
         let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
         let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
-        let reward: u64 = fixed_pool.remove_nft(
+        let (reward, item) = fixed_pool.remove_nft(
             ctx.accounts.owner.key(),
             ctx.accounts.nft_mint.key(),
+            &ctx.accounts.global_authority,
             timestamp,
         )?;
 
-        fixed_pool.pending_reward += reward;
-
         ctx.accounts.global_authority.total_amount -= 1;
+        ctx.accounts.global_authority.count_stake(&item, false);
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        ctx.accounts.global_authority.emission_rate -= item.rate as u64;
+        let reward = ctx.accounts.global_authority.apply_warmup(&item, reward, timestamp);
+        fixed_pool.pending_reward += reward;
+        let penalty = ctx.accounts.global_authority.apply_early_penalty(&item, timestamp);
+        fixed_pool.charge_penalty(penalty);
 
         let global_authority = ctx.accounts.global_authority.key().clone();
         let owner = ctx.accounts.owner.key().clone();
@@ -273,160 +505,1892 @@ pub mod nft_staking {
             &[vault_stake_bump],
         ];
 
-        if ctx.accounts.global_authority.custodial == false {
-            invoke_signed(
-                &thaw_delegated_account(
-                    ctx.accounts.token_metadata_program.key(),
-                    ctx.accounts.vault_pda.key(),
-                    token_account_info.key(),
-                    ctx.accounts.edition.key(),
-                    ctx.accounts.nft_mint.key(),
-                ),
+        release_nft(
+            ctx.accounts.global_authority.custodial,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.vault_pda,
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.edition,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.token_metadata_program,
+            seeds,
+        )?;
+
+        Ok(())
+    }
+
+    /// Unstakes several NFTs at once. Remaining accounts hold one
+    /// (token account, mint, edition, vault PDA) group per NFT.
+    /// Each NFT costs roughly 35k compute units non-custodial (vault PDA search, thaw and
+    /// revoke) and 20k custodial.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_OWNER))]
+    pub fn withdraw_nfts_from_fixed<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, WithdrawNftsFromFixed<'info>>,
+        _global_bump: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_UNSTAKE)?;
+        require!(
+            ctx.accounts.global_authority.unbonding_period == 0,
+            StakingError::UnbondingRequired
+        );
+        let count = ctx.remaining_accounts.len() / 4;
+        require!(
+            count * 4 == ctx.remaining_accounts.len(),
+            StakingError::InvalidBatchAccounts
+        );
+        charge_action_fee(
+            &mut ctx.accounts.global_authority,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program.to_account_info(),
+            count as u64,
+        )?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let global_authority = ctx.accounts.global_authority.key();
+        let owner = ctx.accounts.owner.key();
+        for group in ctx.remaining_accounts.chunks(4) {
+            let user_token_account = &group[0];
+            let nft_mint = &group[1];
+            let edition = &group[2];
+            let vault_pda = &group[3];
+
+            let token_account = Account::<TokenAccount>::try_from(user_token_account)?;
+            require!(token_account.mint == nft_mint.key(), StakingError::InvalidTokenAccount);
+            let (reward, item) = fixed_pool.remove_nft(
+                owner,
+                nft_mint.key(),
+                &ctx.accounts.global_authority,
+                timestamp,
+            )?;
+            ctx.accounts.global_authority.total_amount -= 1;
+            ctx.accounts.global_authority.count_stake(&item, false);
+            ctx.accounts.global_authority.emission_rate -= item.rate as u64;
+            let reward = ctx.accounts.global_authority.apply_warmup(&item, reward, timestamp);
+            fixed_pool.pending_reward += reward;
+            let penalty = ctx.accounts.global_authority.apply_early_penalty(&item, timestamp);
+            fixed_pool.charge_penalty(penalty);
+
+            let token_account_key = user_token_account.key();
+            let (vault_key, vault_stake_bump) = Pubkey::find_program_address(
                 &[
-                    ctx.accounts.vault_pda.to_account_info(),
-                    ctx.accounts.user_token_account.to_account_info(),
-                    ctx.accounts.edition.to_account_info(),
-                    ctx.accounts.nft_mint.to_account_info()
+                    VAULT_STAKE_SEED.as_bytes(),
+                    global_authority.as_ref(),
+                    owner.as_ref(),
+                    token_account_key.as_ref()
                 ],
-                &[seeds]
-            )?;
-    
-            let cpi_context = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                anchor_spl::token::Revoke {
-                    source: ctx.accounts.user_token_account.to_account_info(),
-                    authority: ctx.accounts.owner.to_account_info()
-                }
+                ctx.program_id
             );
-    
-            anchor_spl::token::revoke(cpi_context)?;
-        } else {
-            let cpi_context = CpiContext::new(
-                ctx.accounts.token_program.to_account_info(),
-                anchor_spl::token::SetAuthority {
-                  current_authority: ctx.accounts.vault_pda.to_account_info().clone(),
-                  account_or_mint: ctx.accounts.user_token_account.to_account_info().clone(),
-                },
-              );
-            
-            anchor_spl::token::set_authority(
-                cpi_context.with_signer(&[&seeds[..]]),
-                AccountOwner,
-                Some(ctx.accounts.owner.key()), 
+            require!(vault_pda.key() == vault_key, StakingError::InvalidBatchAccounts);
+            let seeds = &[
+                VAULT_STAKE_SEED.as_bytes(),
+                global_authority.as_ref(),
+                owner.as_ref(),
+                token_account_key.as_ref(),
+                &[vault_stake_bump],
+            ];
+            release_nft(
+                ctx.accounts.global_authority.custodial,
+                &ctx.accounts.owner.to_account_info(),
+                vault_pda,
+                user_token_account,
+                edition,
+                nft_mint,
+                &ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.token_metadata_program,
+                seeds,
             )?;
-        }        
+        }
+        msg!("Unstaked: {}", count);
 
         Ok(())
     }
 
-    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner))]
-    pub fn claim_reward_all(ctx: Context<ClaimRewardAll>, global_bump: u8) -> Result<()> {
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_STAKE))]
+    pub fn extend_lock(ctx: Context<ExtendLock>, _global_bump: u8, lock_period: u8) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_STAKE)?;
         let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
         let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
-        let reward: u64 = fixed_pool.claim_reward_all(timestamp)?;
-        msg!("Reward: {}", reward);
-        if ctx.accounts.reward_vault.amount < reward {
-            return Err(StakingError::LackLamports.into());
-        }
-        let global_authority = &ctx.accounts.global_authority;
-        let name = global_authority.name.as_bytes();
-        let seeds = &[
-            name,
-            GLOBAL_AUTHORITY_SEED.as_bytes(), 
-            &[global_bump]
-        ];
-        let signer = &[&seeds[..]];
-        let token_program = ctx.accounts.token_program.to_account_info();
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.reward_vault.to_account_info(),
-            to: ctx.accounts.user_reward_account.to_account_info(),
-            authority: ctx.accounts.global_authority.to_account_info(),
-        };
-        token::transfer(
-            CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer),
-            reward,
+        let (item, extended) = fixed_pool.extend_lock(
+            &ctx.accounts.global_authority,
+            ctx.accounts.nft_mint.key(),
+            lock_period,
+            timestamp,
         )?;
 
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.count_stake(&item, false);
+        global_authority.count_stake(&extended, true);
+        global_authority.emission_rate = global_authority.emission_rate - item.rate as u64 + extended.rate as u64;
+        msg!("Lock Time: {}", extended.lock_time);
         Ok(())
     }
 
-    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner))]
-    pub fn claim_reward(ctx: Context<ClaimReward>, global_bump: u8) -> Result<()> {
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_STAKE))]
+    pub fn set_auto_relock(ctx: Context<SetAutoRelock>, _global_bump: u8, enabled: bool) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_STAKE)?;
         let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
         let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
-        let reward: u64 = fixed_pool.claim_reward(
-            ctx.accounts.owner.key(),
+        fixed_pool.set_auto_relock(
+            &ctx.accounts.global_authority,
             ctx.accounts.nft_mint.key(),
+            enabled,
             timestamp,
-        )?;
-        msg!("Reward: {}", reward);
-        if ctx.accounts.reward_vault.amount < reward {
-            return Err(StakingError::LackLamports.into());
-        }
-        let global_authority = &ctx.accounts.global_authority;
-        let name = global_authority.name.as_bytes();
-        let seeds = &[
-            name,
-            GLOBAL_AUTHORITY_SEED.as_bytes(), 
-            &[global_bump]
-        ];
-        let signer = &[&seeds[..]];
-        let token_program = ctx.accounts.token_program.to_account_info();
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.reward_vault.to_account_info(),
-            to: ctx.accounts.user_reward_account.to_account_info(),
-            authority: ctx.accounts.global_authority.to_account_info(),
-        };
-        token::transfer(
-            CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer),
-            reward,
-        )?;
+        )
+    }
 
+    pub fn set_post_lock_rate(
+        ctx: Context<SetPostLockRate>,
+        _global_bump: u8,
+        post_lock_mode: u8,
+        post_lock_rate: u64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            matches!(post_lock_mode, POST_LOCK_KEEP | POST_LOCK_NORMAL | POST_LOCK_CUSTOM),
+            StakingError::InvalidPostLockMode
+        );
+        global_authority.set_post_lock(post_lock_mode, post_lock_rate, Clock::get()?.unix_timestamp);
         Ok(())
     }
 
-    pub fn withdraw_reward(ctx: Context<WithdrawReward>, global_bump: u8, amount: u64) -> Result<()> {
-        let global_authority = &ctx.accounts.global_authority;
-        let name = global_authority.name.as_bytes();
-        let seeds = &[
-            name,
-            GLOBAL_AUTHORITY_SEED.as_bytes(), 
-            &[global_bump]
-        ];
-        let signer = &[&seeds[..]];
-        let token_program = ctx.accounts.token_program.to_account_info();
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.reward_vault.to_account_info(),
-            to: ctx.accounts.claimer_reward_account.to_account_info(),
-            authority: ctx.accounts.global_authority.to_account_info(),
-        };
-        token::transfer(
-            CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer),
-            amount,
-        )?;
-
+    pub fn set_early_unstake(
+        ctx: Context<SetEarlyUnstake>,
+        _global_bump: u8,
+        enabled: bool,
+        penalty_bps: u16,
+        penalty_flat: u64,
+        redistribute: bool,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(penalty_bps as u64 <= BPS_DENOMINATOR, StakingError::InvalidFee);
+        global_authority.early_unstake = enabled;
+        global_authority.early_penalty_bps = penalty_bps;
+        global_authority.early_penalty_flat = penalty_flat;
+        global_authority.redistribute_penalties = redistribute;
         Ok(())
     }
 
-    pub fn close_user_fixed_pool(ctx: Context<CloseUserFixedPool>) -> Result<()> {
-        let fixed_pool = ctx.accounts.user_fixed_pool.load()?;
-        if fixed_pool.item_count == 0 {
-            let dest_account_info = ctx.accounts.owner.to_account_info();
-            let source_account_info = ctx.accounts.user_fixed_pool.to_account_info();
-            let dest_starting_lamports = dest_account_info.lamports();
-            **dest_account_info.lamports.borrow_mut() = dest_starting_lamports
-                .checked_add(source_account_info.lamports())
-                .unwrap();
-            **source_account_info.lamports.borrow_mut() = 0;
-            // source_account_info.fill(0);
-        }
+    /// Starts unstaking when the pool has an unbonding period. Rewards are settled and stop
+    /// accruing, the NFT stays frozen or in custody until complete_unstake.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_OWNER))]
+    pub fn request_unstake(ctx: Context<RequestUnstake>, _global_bump: u8) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_UNSTAKE)?;
+        require!(
+            ctx.accounts.global_authority.unbonding_period > 0,
+            StakingError::UnbondingDisabled
+        );
+        charge_action_fee(
+            &mut ctx.accounts.global_authority,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.treasury,
+            &ctx.accounts.system_program.to_account_info(),
+            1,
+        )?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let (reward, item) = fixed_pool.start_unbonding(
+            ctx.accounts.owner.key(),
+            ctx.accounts.nft_mint.key(),
+            &ctx.accounts.global_authority,
+            timestamp,
+        )?;
+
+        ctx.accounts.global_authority.total_amount -= 1;
+        ctx.accounts.global_authority.count_stake(&item, false);
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        ctx.accounts.global_authority.emission_rate -= item.rate as u64;
+        let reward = ctx.accounts.global_authority.apply_warmup(&item, reward, timestamp);
+        fixed_pool.pending_reward += reward;
+        let penalty = ctx.accounts.global_authority.apply_early_penalty(&item, timestamp);
+        fixed_pool.charge_penalty(penalty);
+        msg!("Unbonding until: {}", timestamp + ctx.accounts.global_authority.unbonding_period);
         Ok(())
     }
-}
 
-// Access control modifiers
-fn user(pool_loader: &AccountLoader<UserPool>, user: &AccountInfo) -> Result<()> {
-    let user_pool = pool_loader.load()?;
-    require!(user_pool.owner == *user.key, StakingError::InvalidUserPool);
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_OWNER))]
+    pub fn complete_unstake(
+        ctx: Context<CompleteUnstake>,
+        _global_bump: u8,
+        vault_stake_bump: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_UNSTAKE)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        fixed_pool.complete_unbonding(
+            ctx.accounts.owner.key(),
+            ctx.accounts.nft_mint.key(),
+            timestamp,
+        )?;
+
+        let global_authority = ctx.accounts.global_authority.key();
+        let owner = ctx.accounts.owner.key();
+        let token_account_info = ctx.accounts.user_token_account.key();
+
+        let seeds = &[
+            VAULT_STAKE_SEED.as_bytes(),
+            global_authority.as_ref(),
+            owner.as_ref(),
+            token_account_info.as_ref(),
+            &[vault_stake_bump],
+        ];
+
+        release_nft(
+            ctx.accounts.global_authority.custodial,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.vault_pda,
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.edition,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.token_metadata_program,
+            seeds,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn set_trait_set_bonus(
+        ctx: Context<SetTraitSetBonus>,
+        _global_bump: u8,
+        trait_set: Vec<u8>,
+        set_bonus_bps: u64,
+        set_bonus_daily: u64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            trait_set.len() <= MAX_TRAITS
+                && trait_set.iter().all(|index| (*index as usize) < global_authority.trait_names.len().min(MAX_TRAITS)),
+            StakingError::InvalidTraitSet
+        );
+        global_authority.set_set_bonus(trait_set, set_bonus_bps, set_bonus_daily, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    pub fn set_holding_tiers(
+        ctx: Context<SetHoldingTiers>,
+        _global_bump: u8,
+        min_counts: Vec<u64>,
+        bonuses_bps: Vec<u64>,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            min_counts.len() == bonuses_bps.len() && min_counts.len() <= MAX_HOLDING_TIERS,
+            StakingError::InvalidHoldingTiers
+        );
+        require!(
+            min_counts.windows(2).all(|pair| pair[0] < pair[1]),
+            StakingError::InvalidHoldingTiers
+        );
+        let tiers = min_counts
+            .iter()
+            .zip(bonuses_bps.iter())
+            .map(|(min_count, bonus_bps)| HoldingTier {
+                min_count: *min_count,
+                bonus_bps: *bonus_bps,
+            })
+            .collect();
+        global_authority.set_holding_tiers(tiers, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    pub fn set_loyalty_tiers(
+        ctx: Context<SetLoyaltyTiers>,
+        _global_bump: u8,
+        min_days: Vec<u16>,
+        multipliers_bps: Vec<u64>,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            min_days.len() == multipliers_bps.len() && min_days.len() <= MAX_LOYALTY_TIERS,
+            StakingError::InvalidLoyaltyTiers
+        );
+        require!(
+            min_days.windows(2).all(|pair| pair[0] < pair[1]),
+            StakingError::InvalidLoyaltyTiers
+        );
+        let tiers = min_days
+            .iter()
+            .zip(multipliers_bps.iter())
+            .map(|(days, multiplier_bps)| LoyaltyTier {
+                min_staked: *days as i64 * DAY,
+                multiplier_bps: *multiplier_bps,
+            })
+            .collect();
+        global_authority.set_loyalty_tiers(tiers, Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    pub fn set_warmup_period(
+        ctx: Context<SetWarmupPeriod>,
+        _global_bump: u8,
+        warmup_period: i64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(warmup_period >= 0, StakingError::InvalidWarmupPeriod);
+        global_authority.warmup_period = warmup_period;
+        Ok(())
+    }
+
+    pub fn set_unbonding_period(
+        ctx: Context<SetUnbondingPeriod>,
+        _global_bump: u8,
+        unbonding_period: i64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(unbonding_period >= 0, StakingError::InvalidUnbondingPeriod);
+        global_authority.unbonding_period = unbonding_period;
+        Ok(())
+    }
+
+    pub fn set_emergency_unstake(
+        ctx: Context<SetEmergencyUnstake>,
+        _global_bump: u8,
+        enabled: bool,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        require!(
+            ctx.accounts.admin.key() == global_authority.admin,
+            StakingError::InvalidAdmin
+        );
+        global_authority.emergency_unstake = enabled;
+        Ok(())
+    }
+
+    /// Releases the NFT without paying rewards. Anything accrued since the
+    /// last claim is forfeited, lock time and pause flags are ignored.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_OWNER))]
+    pub fn emergency_unstake(
+        ctx: Context<EmergencyUnstake>,
+        _global_bump: u8,
+        vault_stake_bump: u8,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.global_authority.emergency_unstake,
+            StakingError::EmergencyUnstakeDisabled
+        );
+        // No accrual or checkpoint math on this path, the item's rewards since the last
+        // checkpoint are forfeited and its share of the staked totals is dropped as is
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let item = fixed_pool.force_remove_nft(
+            ctx.accounts.owner.key(),
+            ctx.accounts.nft_mint.key(),
+        )?;
+        // Unbonding NFTs already left the staked totals
+        if item.unbonding_end == 0 {
+            let global_authority = &mut ctx.accounts.global_authority;
+            global_authority.total_amount = global_authority.total_amount.saturating_sub(1);
+            global_authority.count_stake(&item, false);
+            global_authority.emission_rate = global_authority.emission_rate.saturating_sub(item.rate as u64);
+        }
+
+        let global_authority = ctx.accounts.global_authority.key();
+        let owner = ctx.accounts.owner.key();
+        let token_account_info = ctx.accounts.user_token_account.key();
+
+        let seeds = &[
+            VAULT_STAKE_SEED.as_bytes(),
+            global_authority.as_ref(),
+            owner.as_ref(),
+            token_account_info.as_ref(),
+            &[vault_stake_bump],
+        ];
+
+        release_nft(
+            ctx.accounts.global_authority.custodial,
+            &ctx.accounts.owner.to_account_info(),
+            &ctx.accounts.vault_pda,
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.edition,
+            &ctx.accounts.nft_mint,
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.token_metadata_program,
+            seeds,
+        )?;
+
+        Ok(())
+    }
+
+    /// Remaining accounts optionally claim every reward stream too, as one
+    /// (stream vault, user token account, treasury token account) group per stream in
+    /// stream order.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_CLAIM))]
+    pub fn claim_reward_all<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ClaimRewardAll<'info>>,
+        global_bump: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_CLAIM)?;
+        ctx.accounts.global_authority.check_reward_source(false)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let owner = fixed_pool.owner;
+        let reward: u64 = fixed_pool.claim_reward_all(&ctx.accounts.global_authority, timestamp)?;
+        msg!("Reward: {}", reward);
+        // Beneficiary token accounts come first in remaining accounts, followed by stream accounts
+        let beneficiary_count = fixed_pool.beneficiary_count as usize;
+        require!(
+            ctx.remaining_accounts.len() >= beneficiary_count,
+            StakingError::InvalidBeneficiaryAccounts
+        );
+        let (beneficiary_accounts, stream_accounts) = ctx.remaining_accounts.split_at(beneficiary_count);
+        payout_reward(
+            &mut ctx.accounts.global_authority,
+            &mut fixed_pool,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.treasury_reward_account,
+            &ctx.accounts.user_reward_account,
+            beneficiary_accounts,
+            &ctx.accounts.token_program,
+            global_bump,
+            reward,
+            timestamp,
+        )?;
+
+        if !stream_accounts.is_empty() {
+            let stream_count = ctx.accounts.global_authority.reward_streams.len();
+            require!(
+                stream_accounts.len() == stream_count * 3,
+                StakingError::InvalidStreamAccounts
+            );
+            for stream_index in 0..stream_count {
+                let reward = fixed_pool.claim_stream(&ctx.accounts.global_authority, stream_index, timestamp)?;
+                let stream_vault = &stream_accounts[stream_index * 3];
+                let user_stream_account = &stream_accounts[stream_index * 3 + 1];
+                let treasury_stream_account = &stream_accounts[stream_index * 3 + 2];
+                pay_stream_reward(
+                    &mut ctx.accounts.global_authority,
+                    stream_index,
+                    stream_vault,
+                    user_stream_account,
+                    treasury_stream_account,
+                    &owner,
+                    &ctx.accounts.token_program.to_account_info(),
+                    global_bump,
+                    reward,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remaining accounts are the beneficiary token accounts in beneficiary order.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_CLAIM))]
+    pub fn claim_reward<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ClaimReward<'info>>,
+        global_bump: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_CLAIM)?;
+        ctx.accounts.global_authority.check_reward_source(false)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let owner = fixed_pool.owner;
+        let reward: u64 = fixed_pool.claim_reward(
+            owner,
+            ctx.accounts.nft_mint.key(),
+            &ctx.accounts.global_authority,
+            timestamp,
+        )?;
+        msg!("Reward: {}", reward);
+        payout_reward(
+            &mut ctx.accounts.global_authority,
+            &mut fixed_pool,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.treasury_reward_account,
+            &ctx.accounts.user_reward_account,
+            ctx.remaining_accounts,
+            &ctx.accounts.token_program,
+            global_bump,
+            reward,
+            timestamp,
+        )?;
+
+        Ok(())
+    }
+
+    /// The delegate can claim every reward on the owner's behalf, always paid to the owner.
+    /// With can_stake it can also stake into this pool, including NFTs from its own wallet,
+    /// but only the owner can unstake, so staked NFTs are returned to the owner.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_OWNER))]
+    pub fn set_delegate(ctx: Context<SetDelegate>, delegate: Pubkey, can_stake: bool) -> Result<()> {
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        fixed_pool.delegate = delegate;
+        fixed_pool.delegate_can_stake = can_stake as u64;
+        Ok(())
+    }
+
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_OWNER))]
+    pub fn set_beneficiaries(
+        ctx: Context<SetBeneficiaries>,
+        wallets: Vec<Pubkey>,
+        shares: Vec<u16>,
+    ) -> Result<()> {
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        fixed_pool.set_beneficiaries(&wallets, &shares)?;
+        Ok(())
+    }
+
+    /// Remaining accounts are the beneficiary token accounts in beneficiary order.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_CLAIM))]
+    pub fn release_vested<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ReleaseVested<'info>>,
+        global_bump: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_CLAIM)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let amount = fixed_pool.release_vested(&ctx.accounts.global_authority, timestamp);
+        msg!("Released: {}, Locked: {}", amount, fixed_pool.unreleased_vesting());
+        if amount == 0 {
+            return Ok(());
+        }
+        pay_split_reward(
+            &mut ctx.accounts.global_authority,
+            &fixed_pool,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.user_reward_account,
+            ctx.remaining_accounts,
+            &ctx.accounts.token_program,
+            global_bump,
+            amount,
+        )?;
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.total_vesting -= amount;
+        Ok(())
+    }
+
+    pub fn set_vesting(
+        ctx: Context<SetVesting>,
+        _global_bump: u8,
+        vesting_cliff: i64,
+        vesting_duration: i64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            vesting_cliff >= 0 && vesting_cliff <= vesting_duration,
+            StakingError::InvalidVestingSchedule
+        );
+        // SOL rewards are always paid out instantly
+        require!(
+            vesting_duration == 0 || global_authority.reward_source != REWARD_SOURCE_SOL,
+            StakingError::InvalidRewardSource
+        );
+        global_authority.vesting_cliff = vesting_cliff;
+        global_authority.vesting_duration = vesting_duration;
+        Ok(())
+    }
+
+    pub fn set_fees(
+        ctx: Context<SetFees>,
+        _global_bump: u8,
+        claim_fee_bps: u16,
+        action_fee: u64,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            claim_fee_bps as u64 + global_authority.burn_bps as u64 <= BPS_DENOMINATOR,
+            StakingError::InvalidFee
+        );
+        require!(
+            (claim_fee_bps == 0 && action_fee == 0) || treasury != Pubkey::default(),
+            StakingError::InvalidTreasury
+        );
+        global_authority.claim_fee_bps = claim_fee_bps;
+        global_authority.action_fee = action_fee;
+        global_authority.treasury = treasury;
+        Ok(())
+    }
+
+    pub fn set_burn_rate(ctx: Context<SetBurnRate>, _global_bump: u8, burn_bps: u16) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            burn_bps as u64 + global_authority.claim_fee_bps as u64 <= BPS_DENOMINATOR,
+            StakingError::InvalidFee
+        );
+        global_authority.burn_bps = burn_bps;
+        Ok(())
+    }
+
+    /// Remaining accounts are the beneficiary wallets in beneficiary order.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_CLAIM))]
+    pub fn claim_sol_reward_all<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ClaimSolRewardAll<'info>>,
+        _global_bump: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_CLAIM)?;
+        ctx.accounts.global_authority.check_reward_source(true)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        require!(ctx.accounts.pool_owner.key.eq(&fixed_pool.owner), StakingError::InvalidUserPool);
+        let reward: u64 = fixed_pool.claim_reward_all(&ctx.accounts.global_authority, timestamp)?;
+        msg!("Reward: {}", reward);
+        payout_sol_reward(
+            &mut ctx.accounts.global_authority,
+            &mut fixed_pool,
+            &ctx.accounts.sol_vault,
+            *ctx.bumps.get("sol_vault").unwrap(),
+            &ctx.accounts.treasury,
+            &ctx.accounts.pool_owner,
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program.to_account_info(),
+            reward,
+        )?;
+
+        Ok(())
+    }
+
+    /// Remaining accounts are the beneficiary wallets in beneficiary order.
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_CLAIM))]
+    pub fn claim_sol_reward<'a, 'b, 'c, 'info>(
+        ctx: Context<'a, 'b, 'c, 'info, ClaimSolReward<'info>>,
+        _global_bump: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_CLAIM)?;
+        ctx.accounts.global_authority.check_reward_source(true)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.accrue_liability(timestamp);
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let owner = fixed_pool.owner;
+        require!(ctx.accounts.pool_owner.key.eq(&owner), StakingError::InvalidUserPool);
+        let reward: u64 = fixed_pool.claim_reward(
+            owner,
+            ctx.accounts.nft_mint.key(),
+            &ctx.accounts.global_authority,
+            timestamp,
+        )?;
+        msg!("Reward: {}", reward);
+        payout_sol_reward(
+            &mut ctx.accounts.global_authority,
+            &mut fixed_pool,
+            &ctx.accounts.sol_vault,
+            *ctx.bumps.get("sol_vault").unwrap(),
+            &ctx.accounts.treasury,
+            &ctx.accounts.pool_owner,
+            ctx.remaining_accounts,
+            &ctx.accounts.system_program.to_account_info(),
+            reward,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn fund_sol_rewards(ctx: Context<FundSolRewards>, _global_bump: u8, amount: u64) -> Result<()> {
+        invoke(
+            &system_instruction::transfer(
+                &ctx.accounts.funder.key(),
+                &ctx.accounts.sol_vault.key(),
+                amount,
+            ),
+            &[
+                ctx.accounts.funder.to_account_info(),
+                ctx.accounts.sol_vault.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+        ctx.accounts.global_authority.total_deposited += amount;
+        msg!("Deposited: {}", amount);
+        Ok(())
+    }
+
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_CLAIM))]
+    pub fn claim_stream_reward(
+        ctx: Context<ClaimStreamReward>,
+        global_bump: u8,
+        stream_index: u8,
+    ) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_CLAIM)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        let owner = fixed_pool.owner;
+        let reward = fixed_pool.claim_stream(&ctx.accounts.global_authority, stream_index as usize, timestamp)?;
+        pay_stream_reward(
+            &mut ctx.accounts.global_authority,
+            stream_index as usize,
+            &ctx.accounts.stream_vault.to_account_info(),
+            &ctx.accounts.user_stream_account.to_account_info(),
+            &ctx.accounts.treasury_stream_account,
+            &owner,
+            &ctx.accounts.token_program.to_account_info(),
+            global_bump,
+            reward,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn add_reward_stream(
+        ctx: Context<AddRewardStream>,
+        _global_bump: u8,
+        trait_rates: Vec<u64>,
+        normal_rate: u64,
+        lock_rates: Vec<u64>,
+        start_time: i64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(
+            global_authority.reward_streams.len() < MAX_REWARD_STREAMS,
+            StakingError::RewardStreamOverflow
+        );
+        let mint = ctx.accounts.stream_mint.key();
+        require!(!global_authority.is_reward_mint(&mint), StakingError::InvalidRewardStream);
+        global_authority.reward_streams.push(RewardStream {
+            mint,
+            trait_rates,
+            normal_rate,
+            lock_rates,
+            start_time,
+            ..Default::default()
+        });
+        Ok(())
+    }
+
+    pub fn update_reward_stream(
+        ctx: Context<UpdateRewardStream>,
+        _global_bump: u8,
+        stream_index: u8,
+        trait_rates: Vec<u64>,
+        normal_rate: u64,
+        lock_rates: Vec<u64>,
+        end_time: i64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let stream = global_authority
+            .reward_streams
+            .get_mut(stream_index as usize)
+            .ok_or(StakingError::InvalidRewardStream)?;
+        stream.set_terms(trait_rates, normal_rate, lock_rates, end_time, timestamp);
+        Ok(())
+    }
+
+    /// Permissionless. The sponsor deposits amount, shared pro-rata by the NFTs matching
+    /// the eligibility filter between start_time and end_time. Time without any eligible
+    /// NFT leaves its share of the budget to the sponsor.
+    pub fn create_campaign(
+        ctx: Context<CreateCampaign>,
+        _global_bump: u8,
+        params: CampaignParams,
+    ) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        // A start in the past spreads the budget over what is left of the campaign
+        let start_time = params.start_time.max(timestamp);
+        require!(
+            params.amount >= MIN_CAMPAIGN_AMOUNT
+                && params.end_time > start_time
+                && params.end_time - timestamp <= MAX_CAMPAIGN_DURATION
+                && params.eligibility <= ELIGIBILITY_LOCK_TIER,
+            StakingError::InvalidCampaign
+        );
+
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.sponsor_token_account.to_account_info(),
+            to: ctx.accounts.campaign_vault.to_account_info(),
+            authority: ctx.accounts.sponsor.to_account_info(),
+        };
+        token::transfer(CpiContext::new(token_program, cpi_accounts), params.amount)?;
+
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.advance_campaigns(timestamp);
+        global_authority.add_campaign(CampaignPool {
+            campaign: ctx.accounts.campaign.key(),
+            eligibility: params.eligibility,
+            eligibility_value: params.eligibility_value,
+            amount: params.amount,
+            start_time,
+            end_time: params.end_time,
+            last_update: timestamp,
+            ..Default::default()
+        })?;
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.global_authority = global_authority.key();
+        campaign.sponsor = ctx.accounts.sponsor.key();
+        campaign.campaign_id = params.campaign_id;
+        campaign.mint = ctx.accounts.campaign_mint.key();
+        campaign.amount = params.amount;
+        campaign.start_time = start_time;
+        campaign.end_time = params.end_time;
+        campaign.eligibility = params.eligibility;
+        campaign.eligibility_value = params.eligibility_value;
+        Ok(())
+    }
+
+    #[access_control(user(&ctx.accounts.user_fixed_pool, &ctx.accounts.owner, ACCESS_CLAIM))]
+    pub fn claim_campaign_reward(ctx: Context<ClaimCampaignReward>, global_bump: u8) -> Result<()> {
+        ctx.accounts.global_authority.check_not_paused(PAUSE_CLAIM)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        ctx.accounts.global_authority.advance_campaigns(timestamp);
+        let mut fixed_pool = ctx.accounts.user_fixed_pool.load_mut()?;
+        require!(
+            ctx.accounts.user_campaign_account.owner == fixed_pool.owner,
+            StakingError::InvalidTokenAccount
+        );
+        let reward = fixed_pool.claim_campaign(&ctx.accounts.global_authority, &ctx.accounts.campaign.key())?;
+        msg!("Campaign Reward: {}", reward);
+        // Allocations never exceed the deposit, a short vault is a bug rather than a write-off
+        require!(reward <= ctx.accounts.campaign_vault.amount, StakingError::LackLamports);
+
+        let campaign_vault = ctx.accounts.campaign_vault.to_account_info();
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let fee = take_token_claim_fee(
+            &ctx.accounts.global_authority,
+            &campaign_vault,
+            &ctx.accounts.treasury_campaign_account,
+            &ctx.accounts.campaign.mint,
+            &token_program,
+            global_bump,
+            reward,
+        )?;
+        vault_transfer(
+            &ctx.accounts.global_authority,
+            &campaign_vault,
+            &ctx.accounts.user_campaign_account.to_account_info(),
+            &token_program,
+            global_bump,
+            reward - fee,
+        )?;
+
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.total_claimed += reward;
+        campaign.total_claim_fees += fee;
+        Ok(())
+    }
+
+    /// Ends a campaign early to free its slot. Rewards already allocated stay claimable,
+    /// the sponsor reclaims the rest with reclaim_campaign.
+    pub fn close_campaign(ctx: Context<CloseCampaign>, _global_bump: u8) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        let timestamp = Clock::get()?.unix_timestamp;
+        let slot = global_authority.campaign_slot(&ctx.accounts.campaign.key())?;
+        let end_time = global_authority.end_campaign(slot, timestamp);
+        let campaign = &mut ctx.accounts.campaign;
+        campaign.start_time = campaign.start_time.min(end_time);
+        campaign.end_time = end_time;
+        Ok(())
+    }
+
+    /// Returns the unallocated budget to the sponsor once the campaign has ended. Rewards
+    /// stakers earned stay claimable for CAMPAIGN_CLAIM_WINDOW, after which whatever is
+    /// left goes back to the sponsor and the campaign slot is freed. The slot is freed
+    /// right away once every allocated reward is claimed.
+    pub fn reclaim_campaign(ctx: Context<ReclaimCampaign>, global_bump: u8) -> Result<()> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        let end_time = ctx.accounts.campaign.end_time;
+        require!(timestamp > end_time, StakingError::CampaignNotEnded);
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.advance_campaigns(timestamp);
+        let slot = global_authority.campaign_slot(&ctx.accounts.campaign.key())?;
+        let vault_amount = ctx.accounts.campaign_vault.amount;
+        let outstanding = global_authority.campaigns[slot].outstanding(ctx.accounts.campaign.total_claimed);
+        let amount = if timestamp > end_time + CAMPAIGN_CLAIM_WINDOW || outstanding == 0 {
+            global_authority.campaigns[slot] = CampaignPool::default();
+            vault_amount
+        } else {
+            vault_amount.saturating_sub(outstanding)
+        };
+
+        let global_authority = &ctx.accounts.global_authority;
+        let name = global_authority.name.as_bytes();
+        let seeds = &[
+            name,
+            GLOBAL_AUTHORITY_SEED.as_bytes(),
+            &[global_bump]
+        ];
+        let signer = &[&seeds[..]];
+        let token_program = ctx.accounts.token_program.to_account_info();
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.campaign_vault.to_account_info(),
+            to: ctx.accounts.sponsor_token_account.to_account_info(),
+            authority: ctx.accounts.global_authority.to_account_info(),
+        };
+        token::transfer(
+            CpiContext::new_with_signer(token_program, cpi_accounts, signer),
+            amount,
+        )?;
+        msg!("Reclaimed: {}", amount);
+        Ok(())
+    }
+
+    pub fn set_partial_payouts(
+        ctx: Context<SetPartialPayouts>,
+        _global_bump: u8,
+        partial_payouts: bool,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        global_authority.partial_payouts = partial_payouts;
+        Ok(())
+    }
+
+    pub fn set_reward_source(
+        ctx: Context<SetRewardSource>,
+        _global_bump: u8,
+        reward_source: u8,
+        mint_cap: u64,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(reward_source <= REWARD_SOURCE_SOL, StakingError::InvalidRewardSource);
+        if reward_source == REWARD_SOURCE_MINT {
+            require!(
+                token_interface::unpack_mint(&ctx.accounts.reward_mint)?.mint_authority
+                    == COption::Some(global_authority.key()),
+                StakingError::InvalidMintAuthority
+            );
+            require!(mint_cap >= global_authority.total_minted, StakingError::MintCapExceeded);
+        }
+        if reward_source == REWARD_SOURCE_SOL {
+            require!(!global_authority.is_vesting(), StakingError::InvalidRewardSource);
+        }
+        global_authority.reward_source = reward_source;
+        global_authority.mint_cap = mint_cap;
+        Ok(())
+    }
+
+    pub fn fund_rewards(ctx: Context<FundRewards>, _global_bump: u8, amount: u64) -> Result<()> {
+        let global_key = ctx.accounts.global_authority.key();
+        let mint = ctx.accounts.reward_mint.key();
+        let vault_bump = *ctx.bumps.get("reward_vault").unwrap();
+        token_interface::create_token_account(
+            &ctx.accounts.funder.to_account_info(),
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+            &global_key,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program.to_account_info(),
+            &[REWARD_VAULT_SEED.as_bytes(), global_key.as_ref(), mint.as_ref(), &[vault_bump]],
+        )?;
+        token_interface::check_token_account(
+            &ctx.accounts.funder_token_account,
+            &mint,
+            &ctx.accounts.funder.key(),
+        )?;
+        token_interface::transfer_checked(
+            &ctx.accounts.token_program,
+            &ctx.accounts.funder_token_account,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.funder.to_account_info(),
+            amount,
+            &[],
+        )?;
+        // Only what reaches the vault counts as deposited
+        let fee = token_interface::transfer_fee(&ctx.accounts.reward_mint, amount)?;
+        let amount = amount - fee;
+
+        let timestamp = Clock::get()?.unix_timestamp;
+        let reward_deposit = &mut ctx.accounts.reward_deposit;
+        reward_deposit.global_authority = ctx.accounts.global_authority.key();
+        reward_deposit.mint = mint;
+        reward_deposit.depositor = ctx.accounts.funder.key();
+        reward_deposit.amount += amount;
+        reward_deposit.last_deposit_time = timestamp;
+
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.total_transfer_fees += fee;
+        if mint == global_authority.reward_token_mint {
+            global_authority.total_deposited += amount;
+        } else if let Some(stream) = global_authority.reward_streams.iter_mut().find(|stream| stream.mint == mint) {
+            stream.total_deposited += amount;
+        }
+        msg!("Deposited: {}", amount);
+        Ok(())
+    }
+
+    /// Moves the balance of a reward vault created before the REWARD_VAULT_SEED vault into
+    /// the PDA vault that claims and withdrawals pay from. Only the admin can migrate.
+    pub fn migrate_reward_vault(ctx: Context<MigrateRewardVault>, global_bump: u8) -> Result<()> {
+        let global_authority = &ctx.accounts.global_authority;
+        require!(
+            ctx.accounts.admin.key() == global_authority.admin,
+            StakingError::InvalidAdmin
+        );
+        let global_key = global_authority.key();
+        let mint = global_authority.reward_token_mint;
+        token_interface::check_token_account(&ctx.accounts.legacy_vault, &mint, &global_key)?;
+        let vault_bump = *ctx.bumps.get("reward_vault").unwrap();
+        token_interface::create_token_account(
+            &ctx.accounts.admin.to_account_info(),
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+            &global_key,
+            &ctx.accounts.token_program,
+            &ctx.accounts.system_program.to_account_info(),
+            &[REWARD_VAULT_SEED.as_bytes(), global_key.as_ref(), mint.as_ref(), &[vault_bump]],
+        )?;
+
+        let amount = token_interface::token_amount(&ctx.accounts.legacy_vault)?;
+        let name = global_authority.name.as_bytes();
+        let seeds = &[
+            name,
+            GLOBAL_AUTHORITY_SEED.as_bytes(),
+            &[global_bump]
+        ];
+        let signer = &[&seeds[..]];
+        token_interface::transfer_checked(
+            &ctx.accounts.token_program,
+            &ctx.accounts.legacy_vault,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.global_authority.to_account_info(),
+            amount,
+            signer,
+        )?;
+        let fee = token_interface::transfer_fee(&ctx.accounts.reward_mint, amount)?;
+        // The legacy balance was never booked as deposited
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.total_transfer_fees += fee;
+        global_authority.total_deposited += amount - fee;
+        msg!("Migrated: {}", amount - fee);
+        Ok(())
+    }
+
+    pub fn set_transfer_fee_mode(
+        ctx: Context<SetTransferFeeMode>,
+        _global_bump: u8,
+        transfer_fee_mode: u8,
+    ) -> Result<()> {
+        let global_authority = &mut ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.config_manager, ROLE_CONFIG_MANAGER)?;
+        require!(transfer_fee_mode <= TRANSFER_FEE_GROSS_UP, StakingError::InvalidTransferFeeMode);
+        global_authority.transfer_fee_mode = transfer_fee_mode;
+        Ok(())
+    }
+
+    pub fn vault_runway(ctx: Context<VaultRunway>, _global_bump: u8) -> Result<i64> {
+        let timestamp = Clock::get()?.unix_timestamp;
+        let global_authority = &ctx.accounts.global_authority;
+        let vault_amount = claimable_vault_amount(
+            global_authority,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+        )?;
+        let available = global_authority.available_rewards(vault_amount);
+        let runway = global_authority.runway(available, timestamp);
+        msg!("Runway: {} seconds", runway);
+        Ok(runway)
+    }
+
+    pub fn withdraw_reward(ctx: Context<WithdrawReward>, global_bump: u8, amount: u64) -> Result<()> {
+        let global_authority = &ctx.accounts.global_authority;
+        role(global_authority, &ctx.accounts.claimer, ROLE_TREASURER)?;
+        global_authority.check_not_paused(PAUSE_WITHDRAW)?;
+        token_interface::check_token_account(
+            &ctx.accounts.claimer_reward_account,
+            &global_authority.reward_token_mint,
+            &ctx.accounts.claimer.key(),
+        )?;
+        // In gross-up mode the claimer receives amount and the vault pays the transfer fee on top
+        let gross = if global_authority.transfer_fee_mode == TRANSFER_FEE_GROSS_UP {
+            token_interface::gross_up(&ctx.accounts.reward_mint, amount)?
+        } else {
+            amount
+        };
+        // Vesting rewards stay in the vault until released
+        require!(
+            token_interface::token_amount(&ctx.accounts.reward_vault)? >= gross + global_authority.total_vesting,
+            StakingError::LackLamports
+        );
+        let name = global_authority.name.as_bytes();
+        let seeds = &[
+            name,
+            GLOBAL_AUTHORITY_SEED.as_bytes(), 
+            &[global_bump]
+        ];
+        let signer = &[&seeds[..]];
+        token_interface::transfer_checked(
+            &ctx.accounts.token_program,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.reward_mint,
+            &ctx.accounts.claimer_reward_account,
+            &ctx.accounts.global_authority.to_account_info(),
+            gross,
+            signer,
+        )?;
+        let fee = token_interface::transfer_fee(&ctx.accounts.reward_mint, gross)?;
+
+        let global_authority = &mut ctx.accounts.global_authority;
+        global_authority.total_transfer_fees += fee;
+        global_authority.total_deposited = global_authority.total_deposited.saturating_sub(gross);
+
+        Ok(())
+    }
+
+    pub fn close_user_fixed_pool(ctx: Context<CloseUserFixedPool>) -> Result<()> {
+        let fixed_pool = ctx.accounts.user_fixed_pool.load()?;
+        require!(fixed_pool.owner == ctx.accounts.owner.key(), StakingError::InvalidOwner);
+        require!(fixed_pool.is_empty(), StakingError::PoolNotEmpty);
+        let dest_account_info = ctx.accounts.owner.to_account_info();
+        let source_account_info = ctx.accounts.user_fixed_pool.to_account_info();
+        let dest_starting_lamports = dest_account_info.lamports();
+        **dest_account_info.lamports.borrow_mut() = dest_starting_lamports
+            .checked_add(source_account_info.lamports())
+            .unwrap();
+        **source_account_info.lamports.borrow_mut() = 0;
+        // source_account_info.fill(0);
+        Ok(())
+    }
+}
+
+// Access control modifiers
+// The owner passes every check, the delegate only ACCESS_CLAIM and, when allowed, ACCESS_STAKE
+fn user(pool_loader: &AccountLoader<UserPool>, user: &AccountInfo, access: u8) -> Result<()> {
+    let user_pool = pool_loader.load()?;
+    if user_pool.owner == *user.key {
+        return Ok(());
+    }
+    require!(user_pool.delegate_allows(user.key, access), StakingError::InvalidUserPool);
+    Ok(())
+}
+
+// Checks the metadata account belongs to the mint and lists the collection creator as verified
+fn verify_nft_metadata(
+    global_authority: &GlobalPool,
+    nft_mint: &AccountInfo,
+    mint_metadata: &AccountInfo,
+) -> Result<()> {
+    let (metadata, _) = Pubkey::find_program_address(
+        &[
+            metaplex_token_metadata::state::PREFIX.as_bytes(),
+            metaplex_token_metadata::id().as_ref(),
+            nft_mint.key().as_ref(),
+        ],
+        &metaplex_token_metadata::id(),
+    );
+    require!(
+        metadata == mint_metadata.key(),
+        StakingError::InvaliedMetadata
+    );
+
+    // verify metadata is legit
+    let nft_metadata = Metadata::from_account_info(mint_metadata)?;
+
+    if let Some(creators) = nft_metadata.data.creators {
+        let mut valid: u8 = 0;
+        for creator in creators {
+            if creator.address == global_authority.nft_creator && creator.verified == true
+            {
+                valid = 1;
+                break;
+            }
+        }
+        if valid != 1 {
+            return Err(StakingError::InvalidCollection.into());
+        }
+    } else {
+        return Err(StakingError::MetadataCreatorParseError.into());
+    };
+    Ok(())
+}
+
+fn new_staked_item(
+    global_authority: &GlobalPool,
+    nft_mint: Pubkey,
+    lock_period: u8,
+    role: &str,
+    model: u64,
+    timestamp: i64,
+) -> Result<StakedNFT> {
+    let lock_time = timestamp + DAY * lock_period as i64;
+
+    let mut rate: i64 = 0;
+    let mut tier: u64 = 0;
+    if model == 1 {
+        let index = global_authority
+            .trait_names
+            .iter()
+            .position(|x| x == role)
+            .ok_or(StakingError::UnknownTrait)?;
+        rate = global_authority.trait_rates[index] as i64;
+        tier = index as u64;
+    }
+    if model == 2 {
+        rate = global_authority.normal_rate as i64;
+    }
+    if model == 3 {
+        let index = global_authority.lock_durations.iter().position(|x| *x == lock_period);
+        if let Some(index) = index {
+            rate = global_authority.lock_rates[index] as i64;
+            tier = index as u64;
+        }
+    }
+    Ok(StakedNFT {
+        nft_addr: nft_mint,
+        stake_time: timestamp,
+        reward_time: timestamp,
+        lock_time,
+        rate,
+        model,
+        tier,
+        penalty_index: global_authority.penalty_index,
+        auto_relock: 0,
+        unbonding_end: 0,
+    })
+}
+
+// Freezes a delegated NFT in place or moves a custodial one under the vault PDA
+#[allow(clippy::too_many_arguments)]
+fn lock_nft<'info>(
+    custodial: bool,
+    owner: &AccountInfo<'info>,
+    vault_pda: &AccountInfo<'info>,
+    vault_key: Pubkey,
+    user_token_account: &AccountInfo<'info>,
+    edition: &AccountInfo<'info>,
+    nft_mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    token_metadata_program: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    if !custodial {
+        let cpi_context = CpiContext::new(
+            token_program.clone(),
+            anchor_spl::token::Approve {
+                to: user_token_account.clone(),
+                delegate: vault_pda.clone(),
+                authority: owner.clone()
+            }
+        );
+
+        anchor_spl::token::approve(cpi_context, 1)?;
+        invoke_signed(
+            &freeze_delegated_account(
+                token_metadata_program.key(),
+                vault_pda.key(),
+                user_token_account.key(),
+                edition.key(),
+                nft_mint.key(),
+            ),
+            &[
+                vault_pda.clone(),
+                user_token_account.clone(),
+                edition.clone(),
+                nft_mint.clone()
+            ],
+            &[seeds]
+        )?;
+    } else {
+        let cpi_context = CpiContext::new(
+            token_program.clone(),
+            anchor_spl::token::SetAuthority {
+                current_authority: owner.clone(),
+                account_or_mint: user_token_account.clone(),
+            },
+        );
+
+        anchor_spl::token::set_authority(cpi_context, AccountOwner, Some(vault_key))?;
+    }
+    Ok(())
+}
+
+// Thaws a delegated NFT or hands a custodial one back to its owner
+#[allow(clippy::too_many_arguments)]
+fn release_nft<'info>(
+    custodial: bool,
+    owner: &AccountInfo<'info>,
+    vault_pda: &AccountInfo<'info>,
+    user_token_account: &AccountInfo<'info>,
+    edition: &AccountInfo<'info>,
+    nft_mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    token_metadata_program: &AccountInfo<'info>,
+    seeds: &[&[u8]],
+) -> Result<()> {
+    if !custodial {
+        invoke_signed(
+            &thaw_delegated_account(
+                token_metadata_program.key(),
+                vault_pda.key(),
+                user_token_account.key(),
+                edition.key(),
+                nft_mint.key(),
+            ),
+            &[
+                vault_pda.clone(),
+                user_token_account.clone(),
+                edition.clone(),
+                nft_mint.clone()
+            ],
+            &[seeds]
+        )?;
+
+        let cpi_context = CpiContext::new(
+            token_program.clone(),
+            anchor_spl::token::Revoke {
+                source: user_token_account.clone(),
+                authority: owner.clone()
+            }
+        );
+
+        anchor_spl::token::revoke(cpi_context)?;
+    } else {
+        let cpi_context = CpiContext::new(
+            token_program.clone(),
+            anchor_spl::token::SetAuthority {
+                current_authority: vault_pda.clone(),
+                account_or_mint: user_token_account.clone(),
+            },
+        );
+
+        anchor_spl::token::set_authority(
+            cpi_context.with_signer(&[seeds]),
+            AccountOwner,
+            Some(owner.key()),
+        )?;
+    }
+    Ok(())
+}
+
+// Vault balance that can be paid out, excluding vesting rewards and leaving room
+// for the transfer fee when grossing up
+fn claimable_vault_amount(
+    global_authority: &GlobalPool,
+    reward_vault: &AccountInfo,
+    reward_mint: &AccountInfo,
+) -> Result<u64> {
+    let amount = token_interface::token_amount(reward_vault)?.saturating_sub(global_authority.total_vesting);
+    if global_authority.transfer_fee_mode == TRANSFER_FEE_GROSS_UP {
+        return Ok(amount - token_interface::transfer_fee(reward_mint, amount)?);
+    }
+    Ok(amount)
+}
+
+// Sends a settled reward out of the vault, or mints it in mint-on-claim mode.
+// Books the transfer fee and, in gross-up mode, the extra sent on top of the
+// settled amount so the totals match what left the vault.
+fn pay_reward<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    reward_vault: &AccountInfo<'info>,
+    reward_mint: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    global_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    let name = global_authority.name.as_bytes();
+    let seeds = &[
+        name,
+        GLOBAL_AUTHORITY_SEED.as_bytes(),
+        &[global_bump]
+    ];
+    let signer = &[&seeds[..]];
+    if global_authority.reward_source == REWARD_SOURCE_MINT {
+        token_interface::mint_to(
+            token_program,
+            reward_mint,
+            to,
+            &global_authority.to_account_info(),
+            amount,
+            signer,
+        )?;
+        return Ok(());
+    }
+    let gross = if global_authority.transfer_fee_mode == TRANSFER_FEE_GROSS_UP {
+        token_interface::gross_up(reward_mint, amount)?
+    } else {
+        amount
+    };
+    token_interface::transfer_checked(
+        token_program,
+        reward_vault,
+        reward_mint,
+        to,
+        &global_authority.to_account_info(),
+        gross,
+        signer,
+    )?;
+    global_authority.total_transfer_fees += token_interface::transfer_fee(reward_mint, gross)?;
+    global_authority.total_distributed += gross - amount;
+    Ok(())
+}
+
+// Pays a reward stream from its vault after checking the vault and destination accounts.
+// The claim fee is sent to the treasury's account for the stream mint.
+#[allow(clippy::too_many_arguments)]
+fn pay_stream_reward<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    stream_index: usize,
+    stream_vault: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    treasury_account: &AccountInfo<'info>,
+    owner: &Pubkey,
+    token_program: &AccountInfo<'info>,
+    global_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    // Reward streams are spl-token only
+    require!(token_program.key() == token::ID, StakingError::InvalidTokenProgram);
+    let mint = global_authority.reward_streams[stream_index].mint;
+    let (vault_pda, _) = Pubkey::find_program_address(
+        &[
+            REWARD_VAULT_SEED.as_bytes(),
+            global_authority.key().as_ref(),
+            mint.as_ref(),
+        ],
+        &crate::ID,
+    );
+    require!(stream_vault.key() == vault_pda, StakingError::InvalidStreamAccounts);
+    let vault = Account::<TokenAccount>::try_from(stream_vault)?;
+    let user_account = Account::<TokenAccount>::try_from(to)?;
+    require!(
+        user_account.mint == mint && user_account.owner == *owner,
+        StakingError::InvalidStreamAccounts
+    );
+    require!(vault.amount >= amount, StakingError::LackLamports);
+    msg!("Stream {} Reward: {}", stream_index, amount);
+
+    let fee = take_token_claim_fee(
+        global_authority,
+        stream_vault,
+        treasury_account,
+        &mint,
+        token_program,
+        global_bump,
+        amount,
+    )?;
+    vault_transfer(global_authority, stream_vault, to, token_program, global_bump, amount - fee)?;
+
+    let stream = &mut global_authority.reward_streams[stream_index];
+    stream.total_distributed += amount;
+    stream.total_claim_fees += fee;
+    Ok(())
+}
+
+// Sends amount out of a spl-token vault owned by the global authority
+fn vault_transfer<'info>(
+    global_authority: &Account<'info, GlobalPool>,
+    vault: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    global_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let name = global_authority.name.as_bytes();
+    let seeds = &[
+        name,
+        GLOBAL_AUTHORITY_SEED.as_bytes(),
+        &[global_bump]
+    ];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = Transfer {
+        from: vault.clone(),
+        to: to.clone(),
+        authority: global_authority.to_account_info(),
+    };
+    token::transfer(
+        CpiContext::new_with_signer(token_program.clone(), cpi_accounts, signer),
+        amount,
+    )
+}
+
+// Lamports the SOL vault can pay out while staying rent exempt
+fn sol_vault_balance(sol_vault: &SystemAccount) -> Result<u64> {
+    let reserve = Rent::get()?.minimum_balance(0);
+    Ok(sol_vault.lamports().saturating_sub(reserve))
+}
+
+fn pay_sol_reward<'info>(
+    global_authority: &Pubkey,
+    sol_vault: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    sol_vault_bump: u8,
+    amount: u64,
+) -> Result<()> {
+    if amount == 0 {
+        return Ok(());
+    }
+    invoke_signed(
+        &system_instruction::transfer(sol_vault.key, to.key, amount),
+        &[sol_vault.clone(), to.clone(), system_program.clone()],
+        &[&[
+            SOL_VAULT_SEED.as_bytes(),
+            global_authority.as_ref(),
+            &[sol_vault_bump],
+        ]],
+    )?;
+    Ok(())
+}
+
+// Settles a claimed reward against the vault and pays out what it covers: the burn share
+// is burned, the claim fee goes to the treasury and the rest vests or is split between
+// the beneficiaries and the owner
+#[allow(clippy::too_many_arguments)]
+fn payout_reward<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    fixed_pool: &mut UserPool,
+    reward_vault: &AccountInfo<'info>,
+    reward_mint: &AccountInfo<'info>,
+    treasury_reward_account: &AccountInfo<'info>,
+    user_reward_account: &AccountInfo<'info>,
+    beneficiary_accounts: &[AccountInfo<'info>],
+    token_program: &AccountInfo<'info>,
+    global_bump: u8,
+    reward: u64,
+    timestamp: i64,
+) -> Result<()> {
+    let vault_amount = claimable_vault_amount(global_authority, reward_vault, reward_mint)?;
+    let reward = fixed_pool.settle_reward(global_authority, reward, vault_amount)?;
+    msg!("Paid: {}, Owed: {}", reward, fixed_pool.reward_debt);
+    let owner = fixed_pool.owner;
+    let burned = burn_claim_share(
+        global_authority,
+        reward_vault,
+        reward_mint,
+        token_program,
+        &owner,
+        global_bump,
+        reward,
+    )?;
+    let reward = take_claim_fee(
+        global_authority,
+        reward_vault,
+        reward_mint,
+        treasury_reward_account,
+        token_program,
+        global_bump,
+        reward,
+    )? - burned;
+    if global_authority.is_vesting() {
+        fixed_pool.add_vesting(reward, timestamp);
+        global_authority.total_vesting += reward;
+        msg!("Vesting: {}", reward);
+        return Ok(());
+    }
+    pay_split_reward(
+        global_authority,
+        fixed_pool,
+        reward_vault,
+        reward_mint,
+        user_reward_account,
+        beneficiary_accounts,
+        token_program,
+        global_bump,
+        reward,
+    )
+}
+
+// SOL counterpart of payout_reward, SOL rewards are never burned or vested
+#[allow(clippy::too_many_arguments)]
+fn payout_sol_reward<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    fixed_pool: &mut UserPool,
+    sol_vault: &SystemAccount<'info>,
+    sol_vault_bump: u8,
+    treasury: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+    beneficiary_accounts: &[AccountInfo<'info>],
+    system_program: &AccountInfo<'info>,
+    reward: u64,
+) -> Result<()> {
+    let reward = fixed_pool.settle_reward(global_authority, reward, sol_vault_balance(sol_vault)?)?;
+    msg!("Paid: {}, Owed: {}", reward, fixed_pool.reward_debt);
+    let reward = take_sol_claim_fee(
+        global_authority,
+        &sol_vault.to_account_info(),
+        treasury,
+        system_program,
+        sol_vault_bump,
+        reward,
+    )?;
+    pay_split_sol_reward(
+        &global_authority.key(),
+        fixed_pool,
+        &sol_vault.to_account_info(),
+        owner,
+        beneficiary_accounts,
+        system_program,
+        sol_vault_bump,
+        reward,
+    )
+}
+
+// Pays a reward token payout split between the beneficiaries and the owner,
+// who keeps whatever the beneficiary shares leave
+#[allow(clippy::too_many_arguments)]
+fn pay_split_reward<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    fixed_pool: &UserPool,
+    reward_vault: &AccountInfo<'info>,
+    reward_mint: &AccountInfo<'info>,
+    user_reward_account: &AccountInfo<'info>,
+    beneficiary_accounts: &[AccountInfo<'info>],
+    token_program: &AccountInfo<'info>,
+    global_bump: u8,
+    reward: u64,
+) -> Result<()> {
+    require!(
+        beneficiary_accounts.len() == fixed_pool.beneficiary_count as usize,
+        StakingError::InvalidBeneficiaryAccounts
+    );
+    let mut owner_reward = reward;
+    for (index, beneficiary_account) in beneficiary_accounts.iter().enumerate() {
+        let share = fixed_pool.beneficiary_share(index, reward);
+        token_interface::check_token_account(
+            beneficiary_account,
+            &global_authority.reward_token_mint,
+            &fixed_pool.beneficiaries[index].wallet,
+        )?;
+        pay_reward(
+            global_authority,
+            reward_vault,
+            reward_mint,
+            beneficiary_account,
+            token_program,
+            global_bump,
+            share,
+        )?;
+        owner_reward -= share;
+    }
+    if owner_reward > 0 {
+        token_interface::check_token_account(
+            user_reward_account,
+            &global_authority.reward_token_mint,
+            &fixed_pool.owner,
+        )?;
+        pay_reward(
+            global_authority,
+            reward_vault,
+            reward_mint,
+            user_reward_account,
+            token_program,
+            global_bump,
+            owner_reward,
+        )?;
+    }
+    Ok(())
+}
+
+// SOL counterpart of pay_split_reward, beneficiary accounts are the wallets themselves
+#[allow(clippy::too_many_arguments)]
+fn pay_split_sol_reward<'info>(
+    global_authority: &Pubkey,
+    fixed_pool: &UserPool,
+    sol_vault: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+    beneficiary_accounts: &[AccountInfo<'info>],
+    system_program: &AccountInfo<'info>,
+    sol_vault_bump: u8,
+    reward: u64,
+) -> Result<()> {
+    require!(
+        beneficiary_accounts.len() == fixed_pool.beneficiary_count as usize,
+        StakingError::InvalidBeneficiaryAccounts
+    );
+    let mut owner_reward = reward;
+    for (index, beneficiary_account) in beneficiary_accounts.iter().enumerate() {
+        let share = fixed_pool.beneficiary_share(index, reward);
+        require!(
+            beneficiary_account.key.eq(&fixed_pool.beneficiaries[index].wallet),
+            StakingError::InvalidBeneficiaryAccounts
+        );
+        pay_sol_reward(global_authority, sol_vault, beneficiary_account, system_program, sol_vault_bump, share)?;
+        owner_reward -= share;
+    }
+    pay_sol_reward(global_authority, sol_vault, owner, system_program, sol_vault_bump, owner_reward)
+}
+
+// Burns the configured share of a claim from the vault. In mint-on-claim mode that share
+// is simply never minted. Returns the burned amount.
+fn burn_claim_share<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    reward_vault: &AccountInfo<'info>,
+    reward_mint: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    owner: &Pubkey,
+    global_bump: u8,
+    reward: u64,
+) -> Result<u64> {
+    let amount = global_authority.burn_share(reward);
+    if amount == 0 {
+        return Ok(0);
+    }
+    if global_authority.reward_source == REWARD_SOURCE_MINT {
+        global_authority.total_minted -= amount;
+    } else {
+        let name = global_authority.name.as_bytes();
+        let seeds = &[
+            name,
+            GLOBAL_AUTHORITY_SEED.as_bytes(),
+            &[global_bump]
+        ];
+        let signer = &[&seeds[..]];
+        token_interface::burn(
+            token_program,
+            reward_vault,
+            reward_mint,
+            &global_authority.to_account_info(),
+            amount,
+            signer,
+        )?;
+    }
+    global_authority.total_burned += amount;
+    emit!(RewardBurned {
+        global_authority: global_authority.key(),
+        owner: *owner,
+        amount,
+        total_burned: global_authority.total_burned,
+    });
+    Ok(amount)
+}
+
+// Sends the protocol cut of a claim to the treasury and returns what is left for the user
+fn take_claim_fee<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    reward_vault: &AccountInfo<'info>,
+    reward_mint: &AccountInfo<'info>,
+    treasury_reward_account: &AccountInfo<'info>,
+    token_program: &AccountInfo<'info>,
+    global_bump: u8,
+    reward: u64,
+) -> Result<u64> {
+    let fee = global_authority.claim_fee(reward);
+    if fee == 0 {
+        return Ok(reward);
+    }
+    token_interface::check_token_account(
+        treasury_reward_account,
+        &global_authority.reward_token_mint,
+        &global_authority.treasury,
+    )?;
+    pay_reward(
+        global_authority,
+        reward_vault,
+        reward_mint,
+        treasury_reward_account,
+        token_program,
+        global_bump,
+        fee,
+    )?;
+    global_authority.total_claim_fees += fee;
+    msg!("Claim fee: {}", fee);
+    Ok(reward - fee)
+}
+
+// Claim fee for rewards paid in another spl-token, sent from vault to the treasury's
+// account for mint. Returns the fee, the caller books it against its own totals.
+fn take_token_claim_fee<'info>(
+    global_authority: &Account<'info, GlobalPool>,
+    vault: &AccountInfo<'info>,
+    treasury_account: &AccountInfo<'info>,
+    mint: &Pubkey,
+    token_program: &AccountInfo<'info>,
+    global_bump: u8,
+    reward: u64,
+) -> Result<u64> {
+    let fee = global_authority.claim_fee(reward);
+    if fee == 0 {
+        return Ok(0);
+    }
+    let account = Account::<TokenAccount>::try_from(treasury_account)?;
+    require!(
+        account.mint == *mint && account.owner == global_authority.treasury,
+        StakingError::InvalidTreasury
+    );
+    vault_transfer(global_authority, vault, treasury_account, token_program, global_bump, fee)?;
+    msg!("Claim fee: {}", fee);
+    Ok(fee)
+}
+
+// Claim fee in SOL reward mode, paid in lamports from the SOL vault to the treasury
+fn take_sol_claim_fee<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    sol_vault: &AccountInfo<'info>,
+    treasury: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    sol_vault_bump: u8,
+    reward: u64,
+) -> Result<u64> {
+    let fee = global_authority.claim_fee(reward);
+    if fee == 0 {
+        return Ok(reward);
+    }
+    require!(treasury.key.eq(&global_authority.treasury), StakingError::InvalidTreasury);
+    pay_sol_reward(
+        &global_authority.key(),
+        sol_vault,
+        treasury,
+        system_program,
+        sol_vault_bump,
+        fee,
+    )?;
+    global_authority.total_claim_fees += fee;
+    msg!("Claim fee: {}", fee);
+    Ok(reward - fee)
+}
+
+// Charges the flat stake/unstake fee in lamports for count NFTs
+fn charge_action_fee<'info>(
+    global_authority: &mut Account<'info, GlobalPool>,
+    payer: &AccountInfo<'info>,
+    treasury: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    count: u64,
+) -> Result<()> {
+    let fee = global_authority.action_fee * count;
+    if fee == 0 {
+        return Ok(());
+    }
+    require!(treasury.key.eq(&global_authority.treasury), StakingError::InvalidTreasury);
+    invoke(
+        &system_instruction::transfer(payer.key, treasury.key, fee),
+        &[payer.clone(), treasury.clone(), system_program.clone()],
+    )?;
+    global_authority.total_action_fees += fee;
+    Ok(())
+}
+
+// Reallocs a program account to space, topping up its rent from payer first
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+) -> Result<()> {
+    let top_up = Rent::get()?.minimum_balance(space).saturating_sub(account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, top_up),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    account.realloc(space, true)?;
+    Ok(())
+}
+
+fn role(global_pool: &GlobalPool, signer: &AccountInfo, role: u8) -> Result<()> {
+    require!(global_pool.has_role(role, signer.key)?, StakingError::Unauthorized);
     Ok(())
 }
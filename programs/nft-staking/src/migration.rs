@@ -0,0 +1,248 @@
+use anchor_lang::prelude::*;
+
+use crate::constants::*;
+use crate::state::*;
+
+// Account layouts deployed before this version, read back by the migrate_* instructions.
+// New fields are only ever appended, so these stay fixed.
+
+// GlobalPool before versioning
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct GlobalPoolV0 {
+    pub name: String,
+    pub admin: Pubkey,
+    pub nft_creator: Pubkey,
+    pub reward_token_mint: Pubkey,
+    pub total_amount: u64,
+    pub trait_rates: Vec<u64>,
+    pub trait_names: Vec<String>,
+    pub normal_rate: u64,
+    pub lock_durations: Vec<u8>,
+    pub lock_rates: Vec<u64>,
+    pub custodial: bool,
+}
+
+impl GlobalPoolV0 {
+    // Space initialize_global reserved, which is how migrate_global recognizes it
+    pub const LEN: usize = (8 + 10) // name
+        + 32 * 3 // admin, nft_creator, reward_token_mint
+        + 8 // total_amount
+        + (8 + 8 * 5) // trait_rates
+        + (8 + (10 + 8) * 5) // trait_names
+        + 8 // normal_rate
+        + (8 + 3) // lock_durations
+        + (8 + 8 * 3) // lock_rates
+        + 1; // custodial
+}
+
+impl GlobalPool {
+    // Upgrades a version 0 pool, everything added since starts at its default
+    pub fn from_v0(old: GlobalPoolV0) -> GlobalPool {
+        GlobalPool {
+            name: old.name,
+            admin: old.admin,
+            nft_creator: old.nft_creator,
+            reward_token_mint: old.reward_token_mint,
+            total_amount: old.total_amount,
+            trait_rates: old.trait_rates,
+            trait_names: old.trait_names,
+            normal_rate: old.normal_rate,
+            lock_durations: old.lock_durations,
+            lock_rates: old.lock_rates,
+            custodial: old.custodial,
+            version: GLOBAL_POOL_VERSION,
+            ..Default::default()
+        }
+    }
+
+    // Rebuilds a version 0 item. Its tier was never stored, so it is recovered from
+    // the trait rate for model 1 and from the lock period for model 3.
+    pub fn upgrade_item(&self, old: &StakedNFTV0) -> StakedNFT {
+        let tier = match old.model {
+            1 => self.trait_rates.iter().position(|x| *x as i64 == old.rate),
+            3 => {
+                let lock_period = (old.lock_time - old.stake_time) / DAY;
+                self.lock_durations.iter().position(|x| *x as i64 == lock_period)
+            }
+            _ => None,
+        };
+        StakedNFT {
+            nft_addr: old.nft_addr,
+            stake_time: old.stake_time,
+            reward_time: old.reward_time,
+            lock_time: old.lock_time,
+            rate: old.rate,
+            model: old.model,
+            tier: tier.unwrap_or(0) as u64,
+            // Forfeits redistributed before the migration are not owed to old stakes
+            penalty_index: self.penalty_index,
+            ..Default::default()
+        }
+    }
+}
+
+// StakedNFT before any field was appended
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct StakedNFTV0 {
+    pub nft_addr: Pubkey,
+    pub stake_time: i64,
+    pub reward_time: i64,
+    pub lock_time: i64,
+    pub rate: i64,
+    pub model: u64,
+}
+
+// UserPool before any field was appended, keeping only the staked items
+pub struct UserPoolV0 {
+    pub owner: Pubkey,
+    pub items: Vec<StakedNFTV0>,
+    pub reward_time: i64,
+    pub pending_reward: u64,
+}
+
+impl UserPoolV0 {
+    pub const LEN: usize = 32 + 8 + (32 + 8 * 5) * NFT_STAKE_MAX_COUNT + 8 + 8;
+
+    // Reads the account data after the discriminator
+    pub fn read(data: &[u8]) -> Result<UserPoolV0> {
+        let mut data = data;
+        let owner = Pubkey::deserialize(&mut data)?;
+        let item_count = u64::deserialize(&mut data)? as usize;
+        let mut items = Vec::with_capacity(item_count.min(NFT_STAKE_MAX_COUNT));
+        for index in 0..NFT_STAKE_MAX_COUNT {
+            let item = StakedNFTV0::deserialize(&mut data)?;
+            if index < item_count {
+                items.push(item);
+            }
+        }
+        Ok(UserPoolV0 {
+            owner,
+            items,
+            reward_time: i64::deserialize(&mut data)?,
+            pending_reward: u64::deserialize(&mut data)?,
+        })
+    }
+}
+
+impl UserPool {
+    // Rebuilds a zeroed pool from its version 0 layout. Returns the rewards the items
+    // accrued before the migration, which the global liabilities do not include yet.
+    pub fn migrate(&mut self, old: &UserPoolV0, global: &GlobalPool, now: i64) -> u64 {
+        self.owner = old.owner;
+        self.reward_time = old.reward_time;
+        self.pending_reward = old.pending_reward;
+        // Streams, bonuses and campaigns only pay the old items from the migration on
+        self.stream_reward_time = [now; MAX_REWARD_STREAMS];
+        self.holding_time = now;
+        self.set_time = now;
+        self.checkpoint_campaigns(global);
+        for item in old.items.iter() {
+            self.add_nft(global.upgrade_item(item));
+        }
+        (0..self.item_count as usize).map(|index| self.accrued(index, global, now)).sum::<u64>() + old.pending_reward
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn global_pool_v0_keeps_settings_and_sets_version() {
+        let old = GlobalPoolV0 {
+            name: "pool".to_string(),
+            admin: Pubkey::new_unique(),
+            total_amount: 3,
+            trait_rates: vec![1, 2],
+            normal_rate: 10,
+            lock_durations: vec![10],
+            lock_rates: vec![100],
+            custodial: true,
+            ..Default::default()
+        };
+        let mut data = old.try_to_vec().unwrap();
+        assert!(data.len() <= GlobalPoolV0::LEN);
+        data.resize(GlobalPoolV0::LEN, 0);
+        let global = GlobalPool::from_v0(GlobalPoolV0::deserialize(&mut &data[..]).unwrap());
+        assert_eq!(global.admin, old.admin);
+        assert_eq!(global.total_amount, 3);
+        assert_eq!(global.trait_rates, vec![1, 2]);
+        assert_eq!(global.lock_rates, vec![100]);
+        assert!(global.custodial);
+        assert_eq!(global.version, GLOBAL_POOL_VERSION);
+        assert_eq!(global.config_manager, Pubkey::default());
+    }
+
+    fn v0_data(items: &[StakedNFTV0], pending_reward: u64) -> Vec<u8> {
+        let mut data = Pubkey::new_from_array([7; 32]).try_to_vec().unwrap();
+        data.extend((items.len() as u64).to_le_bytes());
+        for index in 0..NFT_STAKE_MAX_COUNT {
+            data.extend(items.get(index).copied().unwrap_or_default().try_to_vec().unwrap());
+        }
+        data.extend(DAY.to_le_bytes());
+        data.extend(pending_reward.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn user_pool_v0_reads_staked_items() {
+        let old = StakedNFTV0 { nft_addr: Pubkey::new_from_array([1; 32]), stake_time: 5, reward_time: 6, lock_time: 7, rate: 8, model: 2 };
+        let data = v0_data(&[old], 40);
+        assert_eq!(data.len(), UserPoolV0::LEN);
+        let pool = UserPoolV0::read(&data).unwrap();
+        assert_eq!(pool.owner, Pubkey::new_from_array([7; 32]));
+        assert_eq!(pool.items.len(), 1);
+        assert_eq!(pool.items[0].lock_time, 7);
+        assert_eq!(pool.reward_time, DAY);
+        assert_eq!(pool.pending_reward, 40);
+        assert!(UserPoolV0::read(&data[..UserPoolV0::LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn user_pool_migration_keeps_items_and_pending_reward() {
+        let staked = StakedNFTV0 { nft_addr: Pubkey::new_from_array([1; 32]), stake_time: 5, reward_time: 6, lock_time: 7, rate: 8, model: 2 };
+        let old = UserPoolV0::read(&v0_data(&[staked, staked], 40)).unwrap();
+        let campaign = CampaignPool { campaign: Pubkey::new_unique(), index: 50, ..Default::default() };
+        let global = GlobalPool { campaigns: vec![campaign.clone()], ..Default::default() };
+        let mut pool = UserPool::default();
+        let owed = pool.migrate(&old, &global, DAY + 6);
+        assert_eq!(pool.owner, Pubkey::new_from_array([7; 32]));
+        assert_eq!(pool.item_count, 2);
+        assert_eq!(pool.items[1].nft_addr, staked.nft_addr);
+        assert_eq!(pool.items[1].reward_time, 6);
+        assert_eq!(pool.items[1].rate, 8);
+        assert_eq!(pool.reward_time, DAY);
+        assert_eq!(pool.pending_reward, 40);
+        assert_eq!(pool.reward_debt, 0);
+        assert_eq!(pool.stream_reward_time, [DAY + 6; MAX_REWARD_STREAMS]);
+        assert_eq!(pool.holding_time, DAY + 6);
+        assert_eq!(pool.set_time, DAY + 6);
+        // Nothing allocated to the campaign before the migration is owed
+        assert_eq!(pool.campaign_shares[0].campaign, campaign.campaign);
+        assert_eq!(pool.campaign_shares[0].index, 50);
+        assert_eq!(pool.campaign_shares[0].pending, 0);
+        // A day at 8 for each item on top of the pending reward
+        assert_eq!(owed, 8 + 8 + 40);
+    }
+
+    #[test]
+    fn upgraded_items_recover_their_tier() {
+        let global = GlobalPool {
+            trait_rates: vec![10, 20, 30],
+            lock_durations: vec![7, 14, 30],
+            lock_rates: vec![70, 140, 300],
+            penalty_index: 9,
+            ..Default::default()
+        };
+        let trait_item = StakedNFTV0 { rate: 30, model: 1, ..Default::default() };
+        assert_eq!(global.upgrade_item(&trait_item).tier, 2);
+        let lock_item = StakedNFTV0 { stake_time: DAY, lock_time: 15 * DAY, rate: 140, model: 3, ..Default::default() };
+        let upgraded = global.upgrade_item(&lock_item);
+        assert_eq!(upgraded.tier, 1);
+        assert_eq!(upgraded.lock_time, 15 * DAY);
+        assert_eq!(upgraded.rate, 140);
+        assert_eq!(upgraded.penalty_index, 9);
+        let normal_item = StakedNFTV0 { rate: 5, model: 2, ..Default::default() };
+        assert_eq!(global.upgrade_item(&normal_item).tier, 0);
+    }
+}
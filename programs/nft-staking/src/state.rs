@@ -2,6 +2,7 @@ use anchor_lang::prelude::*;
 
 use crate::constants::*;
 use crate::errors::*;
+use crate::migration::GlobalPoolV0;
 
 #[account]
 #[derive(Default)]
@@ -17,10 +18,791 @@ pub struct GlobalPool {
     pub lock_durations: Vec<u8>,
     pub lock_rates: Vec<u64>,
     pub custodial: bool,
+    // GLOBAL_POOL_VERSION, older accounts are upgraded by migrate_global
+    pub version: u8,
+    // Role holders, Pubkey::default() when unassigned. The admin holds every role.
+    pub config_manager: Pubkey,
+    pub treasurer: Pubkey,
+    pub pauser: Pubkey,
+    pub trait_oracle: Pubkey,
+    // Bitmask of PAUSE_* flags
+    pub paused: u8,
+    pub exclude_paused_time: bool,
+    // Windows with a PAUSE_ACCRUAL operation paused, excluded from accrual. end == 0 while
+    // still open. Windows are kept forever so past paused time never starts accruing again,
+    // once full the closest ones are merged.
+    pub pause_windows: Vec<PauseWindow>,
+    pub emergency_unstake: bool,
+    // Pay what the vault holds and record the rest as debt instead of failing
+    pub partial_payouts: bool,
+    pub total_debt: u64,
+    // Solvency accounting. total_accrued is accrued but unclaimed rewards as of
+    // accrual_time, emission_rate is the sum of staked rates per day.
+    pub total_accrued: u64,
+    pub total_distributed: u64,
+    pub emission_rate: u64,
+    pub total_deposited: u64,
+    pub accrual_time: i64,
+    // REWARD_SOURCE_VAULT pays from the reward vault, REWARD_SOURCE_MINT mints up to mint_cap,
+    // REWARD_SOURCE_SOL pays lamports from the SOL vault
+    pub reward_source: u8,
+    pub mint_cap: u64,
+    pub total_minted: u64,
+    // Additional reward tokens emitted alongside reward_token_mint
+    pub reward_streams: Vec<RewardStream>,
+    // Staked NFT counts per trait and per lock duration, used to split campaign rewards
+    pub trait_counts: Vec<u64>,
+    pub lock_tier_counts: Vec<u64>,
+    // TRANSFER_FEE_NET credits users net of Token-2022 transfer fees, TRANSFER_FEE_GROSS_UP
+    // sends enough for the user to receive the full reward
+    pub transfer_fee_mode: u8,
+    pub total_transfer_fees: u64,
+    // Claimed rewards unlock linearly over vesting_duration after vesting_cliff,
+    // paid out instantly while vesting_duration is 0
+    pub vesting_cliff: i64,
+    pub vesting_duration: i64,
+    // Claimed but unreleased rewards held back in the reward vault
+    pub total_vesting: u64,
+    // Protocol cut of each claim in basis points and flat lamport fee on stake/unstake,
+    // both sent to treasury. The claim fee applies to every reward source, reward
+    // streams and campaigns keep their own fee totals.
+    pub treasury: Pubkey,
+    pub claim_fee_bps: u16,
+    pub action_fee: u64,
+    pub total_claim_fees: u64,
+    pub total_action_fees: u64,
+    // Share of each claim burned in basis points
+    pub burn_bps: u16,
+    pub total_burned: u64,
+    // Opt-in exit from lock tiers before lock_time. The penalty is early_penalty_bps of the
+    // lock bonus accrued since the lock started plus early_penalty_flat tokens, pro-rated by
+    // the remaining lock time. Forfeits stay in the vault unless redistributed to stakers.
+    pub early_unstake: bool,
+    pub early_penalty_bps: u16,
+    pub early_penalty_flat: u64,
+    pub redistribute_penalties: bool,
+    pub total_penalties: u64,
+    // Redistributed forfeits per staked NFT, with the undivided remainder carried over
+    pub penalty_index: u64,
+    pub penalty_carry: u64,
+    // Rate of a lock tier NFT once lock_time has passed without relocking. POST_LOCK_KEEP
+    // keeps the lock rate, POST_LOCK_NORMAL drops to normal_rate, POST_LOCK_CUSTOM to post_lock_rate.
+    // emission_rate still counts the lock rate, so accrued liability errs on the high side.
+    // Replaced settings stay in past_post_lock, oldest first.
+    pub post_lock_mode: u8,
+    pub post_lock_rate: u64,
+    pub past_post_lock: Vec<PastPostLock>,
+    // Cooldown in seconds between request_unstake and complete_unstake, 0 to unstake directly
+    pub unbonding_period: i64,
+    // Seconds an NFT must stay staked before its rewards can be claimed. Unstaking sooner
+    // forfeits them, stream rewards only start once the warmup has passed.
+    pub warmup_period: i64,
+    // Rate multipliers by continuous staking time, ascending by min_staked. emission_rate
+    // ignores them, so accrued liability reads low while boosts are active. Replaced tiers
+    // stay in past_loyalty_tiers, oldest first.
+    pub loyalty_tiers: Vec<LoyaltyTier>,
+    pub past_loyalty_tiers: Vec<PastLoyaltyTiers>,
+    // Wallet level bonus on rewards by number of staked NFTs past their warmup, ascending
+    // by min_count. Replaced tiers stay in past_holding_tiers, oldest first, for wallets
+    // that have not checkpointed since.
+    pub holding_tiers: Vec<HoldingTier>,
+    pub past_holding_tiers: Vec<PastHoldingTiers>,
+    // Trait indexes a wallet must all have staked past their warmup to earn the set bonus,
+    // either as set_bonus_bps on its base rewards or set_bonus_daily tokens per day.
+    // Replaced configs stay in past_set_bonuses, oldest first.
+    pub trait_set: Vec<u8>,
+    pub set_bonus_bps: u64,
+    pub set_bonus_daily: u64,
+    pub past_set_bonuses: Vec<PastSetBonus>,
+    // Sponsored campaigns by slot, kept until their claim window closes
+    pub campaigns: Vec<CampaignPool>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct HoldingTier {
+    pub min_count: u64,
+    pub bonus_bps: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PastHoldingTiers {
+    // The tiers applied until end_time
+    pub end_time: i64,
+    pub tiers: Vec<HoldingTier>,
+}
+
+impl PastHoldingTiers {
+  pub const LEN: usize = 8 + (8 + 16 * MAX_HOLDING_TIERS);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PastSetBonus {
+    // The set bonus applied until end_time
+    pub end_time: i64,
+    pub trait_set: Vec<u8>,
+    pub set_bonus_bps: u64,
+    pub set_bonus_daily: u64,
+}
+
+impl PastSetBonus {
+  pub const LEN: usize = 8 + (8 + MAX_TRAITS) + 8 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct LoyaltyTier {
+    // Seconds staked since stake_time
+    pub min_staked: i64,
+    pub multiplier_bps: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PastPostLock {
+    // The post-lock setting applied until end_time
+    pub end_time: i64,
+    pub post_lock_mode: u8,
+    pub post_lock_rate: u64,
+}
+
+impl PastPostLock {
+  pub const LEN: usize = 8 + 1 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PastLoyaltyTiers {
+    // The tiers applied until end_time
+    pub end_time: i64,
+    pub tiers: Vec<LoyaltyTier>,
+}
+
+impl PastLoyaltyTiers {
+  pub const LEN: usize = 8 + (8 + 16 * MAX_LOYALTY_TIERS);
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct RewardStream {
+    pub mint: Pubkey,
+    pub trait_rates: Vec<u64>,
+    pub normal_rate: u64,
+    pub lock_rates: Vec<u64>,
+    pub start_time: i64,
+    // 0 while the stream is open ended
+    pub end_time: i64,
+    pub total_deposited: u64,
+    pub total_distributed: u64,
+    pub total_claim_fees: u64,
+    // Replaced rates and end times, oldest first, for wallets that have not checkpointed
+    // the stream since
+    pub past_terms: Vec<PastStreamTerms>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PastStreamTerms {
+    // The terms applied until replaced_at
+    pub replaced_at: i64,
+    pub trait_rates: Vec<u64>,
+    pub normal_rate: u64,
+    pub lock_rates: Vec<u64>,
+    pub end_time: i64,
+}
+
+impl PastStreamTerms {
+  pub const LEN: usize = 8 + (8 + 8 * 5) + 8 + (8 + 8 * 3) + 8;
+}
+
+// Daily rate for a staked item, looked up from a stream's tables
+fn stream_rate(trait_rates: &[u64], normal_rate: u64, lock_rates: &[u64], item: &StakedNFT) -> u64 {
+  let table = match item.model {
+    1 => trait_rates,
+    2 => return normal_rate,
+    3 => lock_rates,
+    _ => return 0,
+  };
+  table.get(item.tier as usize).copied().unwrap_or(0)
+}
+
+impl RewardStream {
+  pub const LEN: usize = 32 + (8 + 8 * 5) + 8 + (8 + 8 * 3) + 8 + 8 + 8 + 8 + 8
+    + (8 + PastStreamTerms::LEN * MAX_BONUS_HISTORY);
+
+  pub fn rate(&self, item: &StakedNFT) -> u64 {
+    stream_rate(&self.trait_rates, self.normal_rate, &self.lock_rates, item)
+  }
+
+  // Replaces the rates and end_time from now on, keeping the old ones like
+  // GlobalPool::set_holding_tiers
+  pub fn set_terms(&mut self, trait_rates: Vec<u64>, normal_rate: u64, lock_rates: Vec<u64>, end_time: i64, now: i64) {
+    let past = PastStreamTerms {
+      replaced_at: now,
+      trait_rates: std::mem::replace(&mut self.trait_rates, trait_rates),
+      normal_rate: std::mem::replace(&mut self.normal_rate, normal_rate),
+      lock_rates: std::mem::replace(&mut self.lock_rates, lock_rates),
+      end_time: std::mem::replace(&mut self.end_time, end_time),
+    };
+    if self.past_terms.len() == MAX_BONUS_HISTORY {
+      self.past_terms.remove(0);
+    }
+    self.past_terms.push(past);
+  }
+
+  // Daily rate for item and the stream end in force at time at
+  pub fn terms_at(&self, item: &StakedNFT, at: i64) -> (u64, i64) {
+    match self.past_terms.iter().find(|past| at < past.replaced_at) {
+      Some(past) => (stream_rate(&past.trait_rates, past.normal_rate, &past.lock_rates, item), past.end_time),
+      None => (self.rate(item), self.end_time),
+    }
+  }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct PauseWindow {
+    pub start: i64,
+    pub end: i64,
 }
 
 impl GlobalPool {
-  pub const LEN: usize = (8 + 10) + 32 + 32 + 32 + 8 + (8 + 8 * 5) + (8 + (10 + 8) * 5) + 8 + (1 * 3 + 8) + (8 * 3 + 8) + 1;
+  pub const LEN: usize = GlobalPoolV0::LEN + 1
+    + 32 * 4 + 1 + 1 + (8 + 16 * MAX_PAUSE_WINDOWS) + 1
+    + 1 + 8 + 8 * 5 + 1 + 8 + 8
+    + (8 + RewardStream::LEN * MAX_REWARD_STREAMS) + (8 + 8 * 5) + (8 + 8 * 3)
+    + 1 + 8
+    + 8 + 8 + 8
+    + 32 + 2 + 8 + 8 + 8
+    + 2 + 8
+    + 1 + 2 + 8 + 1 + 8 + 8 + 8
+    + 1 + 8 + (8 + PastPostLock::LEN * MAX_BONUS_HISTORY)
+    + 8 + 8
+    + (8 + 16 * MAX_LOYALTY_TIERS) + (8 + PastLoyaltyTiers::LEN * MAX_BONUS_HISTORY)
+    + (8 + 16 * MAX_HOLDING_TIERS) + (8 + PastHoldingTiers::LEN * MAX_BONUS_HISTORY)
+    + (8 + MAX_TRAITS) + 8 + 8 + (8 + PastSetBonus::LEN * MAX_BONUS_HISTORY)
+    + (8 + CampaignPool::LEN * MAX_CAMPAIGNS);
+
+  pub fn role_holder(&self, role: u8) -> Result<Pubkey> {
+    match role {
+      ROLE_CONFIG_MANAGER => Ok(self.config_manager),
+      ROLE_TREASURER => Ok(self.treasurer),
+      ROLE_PAUSER => Ok(self.pauser),
+      ROLE_TRAIT_ORACLE => Ok(self.trait_oracle),
+      _ => Err(StakingError::InvalidRole.into()),
+    }
+  }
+
+  pub fn set_role(&mut self, role: u8, holder: Pubkey) -> Result<()> {
+    match role {
+      ROLE_CONFIG_MANAGER => self.config_manager = holder,
+      ROLE_TREASURER => self.treasurer = holder,
+      ROLE_PAUSER => self.pauser = holder,
+      ROLE_TRAIT_ORACLE => self.trait_oracle = holder,
+      _ => return Err(StakingError::InvalidRole.into()),
+    }
+    Ok(())
+  }
+
+  pub fn has_role(&self, role: u8, key: &Pubkey) -> Result<bool> {
+    if self.admin.eq(key) {
+      return Ok(true);
+    }
+    let holder = self.role_holder(role)?;
+    Ok(holder != Pubkey::default() && holder.eq(key))
+  }
+
+  pub fn check_not_paused(&self, flag: u8) -> Result<()> {
+    require!(self.paused & flag == 0, StakingError::OperationPaused);
+    Ok(())
+  }
+
+  // Never fails. Once the window table is full the closest neighbours are merged, the new
+  // window reopening the last one when that gap is the shortest, so the short gap between
+  // them counts as paused as well.
+  pub fn pause(&mut self, flags: u8, now: i64) {
+    if self.exclude_paused_time && flags & PAUSE_ACCRUAL != 0 && self.paused & PAUSE_ACCRUAL == 0 {
+      if self.pause_windows.len() < MAX_PAUSE_WINDOWS {
+        self.pause_windows.push(PauseWindow { start: now, end: 0 });
+      } else {
+        self.merge_pause_window(now);
+      }
+    }
+    self.paused |= flags;
+  }
+
+  fn merge_pause_window(&mut self, now: i64) {
+    let windows = &mut self.pause_windows;
+    let last = windows.len() - 1;
+    let closest = (0..last).min_by_key(|index| windows[index + 1].start - windows[*index].end);
+    match closest {
+      Some(index) if windows[index + 1].start - windows[index].end < now - windows[last].end => {
+        windows[index].end = windows[index + 1].end;
+        windows.remove(index + 1);
+        windows.push(PauseWindow { start: now, end: 0 });
+      }
+      _ => windows[last].end = 0,
+    }
+  }
+
+  pub fn unpause(&mut self, flags: u8, now: i64) {
+    self.paused &= !flags;
+    if self.paused & PAUSE_ACCRUAL == 0 {
+      if let Some(window) = self.pause_windows.last_mut() {
+        if window.end == 0 {
+          window.end = now;
+        }
+      }
+    }
+  }
+
+  pub fn accrued_liability(&self, now: i64) -> u64 {
+    if self.accrual_time == 0 {
+      return self.total_accrued;
+    }
+    let accrued = self.emission_rate as i128 * self.active_seconds(self.accrual_time, now) as i128 / DAY as i128;
+    self.total_accrued.saturating_add(accrued.clamp(0, u64::MAX as i128) as u64)
+  }
+
+  pub fn accrue_liability(&mut self, now: i64) {
+    self.total_accrued = self.accrued_liability(now);
+    self.accrual_time = now;
+  }
+
+  pub fn count_stake(&mut self, item: &StakedNFT, staked: bool) {
+    let counts = match item.model {
+      1 => &mut self.trait_counts,
+      3 => &mut self.lock_tier_counts,
+      _ => return,
+    };
+    let tier = item.tier as usize;
+    if counts.len() <= tier {
+      counts.resize(tier + 1, 0);
+    }
+    if staked {
+      counts[tier] += 1;
+    } else {
+      counts[tier] = counts[tier].saturating_sub(1);
+    }
+  }
+
+  pub fn eligible_count(&self, eligibility: u8, value: u64) -> u64 {
+    match eligibility {
+      ELIGIBILITY_TRAIT => self.trait_counts.get(value as usize).copied().unwrap_or(0),
+      ELIGIBILITY_LOCK_TIER => self.lock_tier_counts.get(value as usize).copied().unwrap_or(0),
+      ELIGIBILITY_ALL => self.total_amount,
+      _ => 0,
+    }
+  }
+
+  // Spreads campaign budgets over the NFTs eligible since the last update. Must run
+  // before anything changes the eligible counts.
+  pub fn advance_campaigns(&mut self, now: i64) {
+    for slot in 0..self.campaigns.len() {
+      let pool = &self.campaigns[slot];
+      let eligible = self.eligible_count(pool.eligibility, pool.eligibility_value);
+      self.campaigns[slot].advance(eligible, now);
+    }
+  }
+
+  // Stops a campaign at now, what it allocated so far stays claimable and the rest goes
+  // back to the sponsor with reclaim_campaign
+  pub fn end_campaign(&mut self, slot: usize, now: i64) -> i64 {
+    self.advance_campaigns(now);
+    let pool = &mut self.campaigns[slot];
+    pool.start_time = pool.start_time.min(now);
+    pool.end_time = pool.end_time.min(now);
+    pool.end_time
+  }
+
+  pub fn campaign_slot(&self, campaign: &Pubkey) -> Result<usize> {
+    self.campaigns
+      .iter()
+      .position(|pool| pool.campaign.eq(campaign))
+      .ok_or_else(|| StakingError::InvalidCampaign.into())
+  }
+
+  pub fn add_campaign(&mut self, pool: CampaignPool) -> Result<()> {
+    if let Some(slot) = self.campaigns.iter().position(|pool| pool.campaign == Pubkey::default()) {
+      self.campaigns[slot] = pool;
+      return Ok(());
+    }
+    require!(self.campaigns.len() < MAX_CAMPAIGNS, StakingError::CampaignOverflow);
+    self.campaigns.push(pool);
+    Ok(())
+  }
+
+  pub fn is_reward_mint(&self, mint: &Pubkey) -> bool {
+    self.reward_token_mint.eq(mint) || self.reward_streams.iter().any(|stream| stream.mint.eq(mint))
+  }
+
+  pub fn check_reward_source(&self, sol: bool) -> Result<()> {
+    require!(
+      (self.reward_source == REWARD_SOURCE_SOL) == sol,
+      StakingError::InvalidRewardSource
+    );
+    Ok(())
+  }
+
+  // Rewards that can be paid out right now
+  pub fn available_rewards(&self, vault_amount: u64) -> u64 {
+    if self.reward_source == REWARD_SOURCE_MINT {
+      return self.mint_cap.saturating_sub(self.total_minted);
+    }
+    vault_amount
+  }
+
+  // Seconds until the vault can no longer cover liabilities at the current emission rate
+  pub fn runway(&self, vault_amount: u64, now: i64) -> i64 {
+    let liabilities = self.accrued_liability(now).saturating_add(self.total_debt);
+    if vault_amount <= liabilities {
+      return 0;
+    }
+    if self.emission_rate == 0 {
+      return i64::MAX;
+    }
+    ((vault_amount - liabilities) as i128 * DAY as i128 / self.emission_rate as i128) as i64
+  }
+
+  pub fn claim_fee(&self, reward: u64) -> u64 {
+    (reward as u128 * self.claim_fee_bps as u128 / BPS_DENOMINATOR as u128) as u64
+  }
+
+  pub fn burn_share(&self, reward: u64) -> u64 {
+    (reward as u128 * self.burn_bps as u128 / BPS_DENOMINATOR as u128) as u64
+  }
+
+  // Replaces the post-lock setting from now on, keeping the old one like set_holding_tiers
+  pub fn set_post_lock(&mut self, post_lock_mode: u8, post_lock_rate: u64, now: i64) {
+    let past = PastPostLock {
+      end_time: now,
+      post_lock_mode: std::mem::replace(&mut self.post_lock_mode, post_lock_mode),
+      post_lock_rate: std::mem::replace(&mut self.post_lock_rate, post_lock_rate),
+    };
+    if self.past_post_lock.len() == MAX_BONUS_HISTORY {
+      self.past_post_lock.remove(0);
+    }
+    self.past_post_lock.push(past);
+  }
+
+  // Daily rate after lock_time under the setting in force at time at, None when the item
+  // keeps its rate
+  pub fn post_lock_rate(&self, item: &StakedNFT, at: i64) -> Option<i64> {
+    if item.model != 3 || item.auto_relock != 0 {
+      return None;
+    }
+    let (post_lock_mode, post_lock_rate) = match self.past_post_lock.iter().find(|past| at < past.end_time) {
+      Some(past) => (past.post_lock_mode, past.post_lock_rate),
+      None => (self.post_lock_mode, self.post_lock_rate),
+    };
+    match post_lock_mode {
+      POST_LOCK_NORMAL => Some(self.normal_rate as i64),
+      POST_LOCK_CUSTOM => Some(post_lock_rate as i64),
+      _ => None,
+    }
+  }
+
+  // Replaces the loyalty tiers from now on, keeping the old ones like set_holding_tiers
+  pub fn set_loyalty_tiers(&mut self, tiers: Vec<LoyaltyTier>, now: i64) {
+    let past = std::mem::replace(&mut self.loyalty_tiers, tiers);
+    if self.past_loyalty_tiers.len() == MAX_BONUS_HISTORY {
+      self.past_loyalty_tiers.remove(0);
+    }
+    self.past_loyalty_tiers.push(PastLoyaltyTiers { end_time: now, tiers: past });
+  }
+
+  pub fn loyalty_tiers_at(&self, at: i64) -> &[LoyaltyTier] {
+    self.past_loyalty_tiers
+      .iter()
+      .find(|past| at < past.end_time)
+      .map(|past| &past.tiers[..])
+      .unwrap_or(&self.loyalty_tiers)
+  }
+
+  pub fn loyalty_multiplier(&self, item: &StakedNFT, at: i64) -> u64 {
+    self.loyalty_tiers_at(at)
+      .iter()
+      .filter(|tier| at - item.stake_time >= tier.min_staked)
+      .map(|tier| tier.multiplier_bps)
+      .max()
+      .unwrap_or(BPS_DENOMINATOR)
+  }
+
+  // Reward of an item between from and to at a daily rate, integrated piecewise over the
+  // loyalty tier boundaries and changes and, when the rate decays to the post-lock rate,
+  // over lock_time and post-lock setting changes
+  pub fn accrual(&self, item: &StakedNFT, rate: i64, decays: bool, from: i64, to: i64) -> u64 {
+    let mut total: i128 = 0;
+    let mut start = from;
+    while start < to {
+      let mut end = to;
+      let boundaries = self.loyalty_tiers_at(start).iter().map(|tier| item.stake_time + tier.min_staked);
+      let changes = self.past_loyalty_tiers.iter().map(|past| past.end_time);
+      for boundary in boundaries.chain(changes) {
+        if boundary > start && boundary < end {
+          end = boundary;
+        }
+      }
+      let segment_rate = if !decays {
+        rate
+      } else if start < item.lock_time {
+        end = end.min(item.lock_time);
+        rate
+      } else {
+        for past in self.past_post_lock.iter() {
+          if past.end_time > start && past.end_time < end {
+            end = past.end_time;
+          }
+        }
+        self.post_lock_rate(item, start).unwrap_or(rate)
+      };
+      let multiplier = self.loyalty_multiplier(item, start);
+      total += segment_rate as i128 * multiplier as i128 * self.active_seconds(start, end) as i128;
+      start = end;
+    }
+    (total / (DAY as i128 * BPS_DENOMINATOR as i128)) as u64
+  }
+
+  // Replaces the holding tiers from now on. Once the history is full the oldest config is
+  // dropped and its time is credited under the next oldest one.
+  pub fn set_holding_tiers(&mut self, tiers: Vec<HoldingTier>, now: i64) {
+    let past = std::mem::replace(&mut self.holding_tiers, tiers);
+    if self.past_holding_tiers.len() == MAX_BONUS_HISTORY {
+      self.past_holding_tiers.remove(0);
+    }
+    self.past_holding_tiers.push(PastHoldingTiers { end_time: now, tiers: past });
+  }
+
+  pub fn holding_tiers_at(&self, at: i64) -> &[HoldingTier] {
+    self.past_holding_tiers
+      .iter()
+      .find(|past| at < past.end_time)
+      .map(|past| &past.tiers[..])
+      .unwrap_or(&self.holding_tiers)
+  }
+
+  // Bonus for count NFTs under the tiers in force at time at
+  pub fn holding_bonus(&self, count: u64, at: i64) -> u64 {
+    self.holding_tiers_at(at)
+      .iter()
+      .filter(|tier| count >= tier.min_count)
+      .map(|tier| tier.bonus_bps)
+      .max()
+      .unwrap_or(0)
+  }
+
+  // Replaces the set bonus from now on, keeping the old one like set_holding_tiers
+  pub fn set_set_bonus(&mut self, trait_set: Vec<u8>, set_bonus_bps: u64, set_bonus_daily: u64, now: i64) {
+    let past = PastSetBonus {
+      end_time: now,
+      trait_set: std::mem::replace(&mut self.trait_set, trait_set),
+      set_bonus_bps: std::mem::replace(&mut self.set_bonus_bps, set_bonus_bps),
+      set_bonus_daily: std::mem::replace(&mut self.set_bonus_daily, set_bonus_daily),
+    };
+    if self.past_set_bonuses.len() == MAX_BONUS_HISTORY {
+      self.past_set_bonuses.remove(0);
+    }
+    self.past_set_bonuses.push(past);
+  }
+
+  // Trait set, set_bonus_bps and set_bonus_daily in force at time at
+  pub fn set_bonus_at(&self, at: i64) -> (&[u8], u64, u64) {
+    match self.past_set_bonuses.iter().find(|past| at < past.end_time) {
+      Some(past) => (&past.trait_set, past.set_bonus_bps, past.set_bonus_daily),
+      None => (&self.trait_set, self.set_bonus_bps, self.set_bonus_daily),
+    }
+  }
+
+  pub fn is_set_complete(&self, trait_counts: &[u64; MAX_TRAITS], at: i64) -> bool {
+    let (trait_set, _, _) = self.set_bonus_at(at);
+    !trait_set.is_empty()
+      && trait_set
+        .iter()
+        .all(|index| trait_counts.get(*index as usize).copied().unwrap_or(0) > 0)
+  }
+
+  pub fn in_warmup(&self, item: &StakedNFT, now: i64) -> bool {
+    now - item.stake_time < self.warmup_period
+  }
+
+  // Forfeits the reward of an NFT unstaked during its warmup
+  pub fn apply_warmup(&mut self, item: &StakedNFT, reward: u64, now: i64) -> u64 {
+    if !self.in_warmup(item, now) {
+      return reward;
+    }
+    self.total_accrued = self.total_accrued.saturating_sub(reward);
+    msg!("Forfeited during warmup: {}", reward);
+    0
+  }
+
+  pub fn lock_duration(&self, item: &StakedNFT) -> i64 {
+    match self.lock_durations.get(item.tier as usize) {
+      Some(days) => *days as i64 * DAY,
+      None => item.lock_time - item.stake_time,
+    }
+  }
+
+  // lock_time with expired auto-relock periods rolled forward past now
+  pub fn current_lock_time(&self, item: &StakedNFT, now: i64) -> i64 {
+    if item.auto_relock == 0 || item.lock_time > now {
+      return item.lock_time;
+    }
+    let duration = self.lock_duration(item);
+    if duration <= 0 {
+      return item.lock_time;
+    }
+    item.lock_time + ((now - item.lock_time) / duration + 1) * duration
+  }
+
+  // Books the penalty of a lock tier NFT leaving before lock_time and returns it for
+  // UserPool::charge_penalty. The lock bonus is what the lock rate paid over normal_rate
+  // since the running lock period started, so claiming first does not lower the penalty.
+  // Must run after the NFT is removed from total_amount.
+  pub fn apply_early_penalty(&mut self, item: &StakedNFT, now: i64) -> u64 {
+    if item.model != 3 || item.lock_time <= now {
+      return 0;
+    }
+    let duration = self.lock_duration(item).max(1);
+    let lock_start = (item.lock_time - duration).max(item.stake_time);
+    let bonus_rate = (item.rate - self.normal_rate as i64).max(0);
+    let lock_bonus = self.accrual(item, bonus_rate, false, lock_start, now);
+    let remaining = (item.lock_time - now) as u128;
+    let full_penalty = lock_bonus as u128 * self.early_penalty_bps as u128 / BPS_DENOMINATOR as u128
+      + self.early_penalty_flat as u128;
+    let penalty = (full_penalty * remaining / duration as u128) as u64;
+    self.total_penalties += penalty;
+    if self.redistribute_penalties && self.total_amount > 0 {
+      let amount = penalty + self.penalty_carry;
+      self.penalty_index += amount / self.total_amount;
+      self.penalty_carry = amount % self.total_amount;
+    } else {
+      self.total_accrued = self.total_accrued.saturating_sub(penalty);
+    }
+    msg!("Early unstake penalty: {}", penalty);
+    penalty
+  }
+
+  pub fn is_vesting(&self) -> bool {
+    self.vesting_duration > 0
+  }
+
+  // Part of amount unlocked for a tranche that started vesting at start_time
+  pub fn vested_amount(&self, amount: u64, start_time: i64, now: i64) -> u64 {
+    let elapsed = now - start_time;
+    if elapsed < self.vesting_cliff {
+      return 0;
+    }
+    if !self.is_vesting() || elapsed >= self.vesting_duration {
+      return amount;
+    }
+    (amount as u128 * elapsed as u128 / self.vesting_duration as u128) as u64
+  }
+
+  // Seconds between from and to that count towards reward accrual
+  pub fn active_seconds(&self, from: i64, to: i64) -> i64 {
+    if to <= from {
+      return 0;
+    }
+    let mut seconds = to - from;
+    for window in self.pause_windows.iter() {
+      let end = if window.end == 0 { to } else { window.end };
+      let overlap = end.min(to) - window.start.max(from);
+      if overlap > 0 {
+        seconds -= overlap;
+      }
+    }
+    seconds
+  }
+}
+
+#[account]
+#[derive(Default)]
+pub struct RewardDeposit {
+    pub global_authority: Pubkey,
+    pub mint: Pubkey,
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub last_deposit_time: i64,
+}
+
+impl RewardDeposit {
+  pub const LEN: usize = 32 + 32 + 32 + 8 + 8;
+}
+
+#[account]
+#[derive(Default)]
+pub struct Campaign {
+    pub global_authority: Pubkey,
+    pub sponsor: Pubkey,
+    pub campaign_id: u64,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub eligibility: u8,
+    pub eligibility_value: u64,
+    // Claim fees are paid out of the campaign budget and included in total_claimed
+    pub total_claimed: u64,
+    pub total_claim_fees: u64,
+}
+
+impl Campaign {
+  pub const LEN: usize = 32 + 32 + 8 + 32 + 8 + 8 + 8 + 1 + 8 + 8 + 8;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CampaignParams {
+    pub campaign_id: u64,
+    pub amount: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    pub eligibility: u8,
+    pub eligibility_value: u64,
+}
+
+// Reward accounting of a running campaign, shared pro-rata by the eligible NFTs
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct CampaignPool {
+    // Pubkey::default() once the slot is free
+    pub campaign: Pubkey,
+    pub eligibility: u8,
+    pub eligibility_value: u64,
+    pub amount: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+    // Campaign seconds with at least one eligible NFT, the budget of the others stays unallocated
+    pub funded_seconds: i64,
+    pub allocated: u64,
+    // Allocated reward per eligible NFT, with the undivided remainder carried over
+    pub index: u64,
+    pub carry: u64,
+    pub last_update: i64,
+}
+
+impl CampaignPool {
+  pub const LEN: usize = 32 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8;
+
+  pub fn is_eligible(&self, item: &StakedNFT) -> bool {
+    match self.eligibility {
+      ELIGIBILITY_TRAIT => item.model == 1 && item.tier == self.eligibility_value,
+      ELIGIBILITY_LOCK_TIER => item.model == 3 && item.tier == self.eligibility_value,
+      ELIGIBILITY_ALL => true,
+      _ => false,
+    }
+  }
+
+  pub fn advance(&mut self, eligible: u64, now: i64) {
+    let from = self.last_update.max(self.start_time);
+    let to = now.min(self.end_time);
+    if to > from && eligible > 0 {
+      self.funded_seconds += to - from;
+      let duration = (self.end_time - self.start_time) as u128;
+      let allocated = (self.amount as u128 * self.funded_seconds as u128 / duration) as u64;
+      let share = allocated - self.allocated + self.carry;
+      self.index += share / eligible;
+      self.carry = share % eligible;
+      self.allocated = allocated;
+    }
+    self.last_update = self.last_update.max(now);
+  }
+
+  // Allocated to staked NFTs and not yet claimed, given the campaign's total_claimed
+  pub fn outstanding(&self, total_claimed: u64) -> u64 {
+    (self.allocated - self.carry).saturating_sub(total_claimed)
+  }
 }
 
 #[zero_copy]
@@ -32,16 +814,70 @@ pub struct StakedNFT {
     pub lock_time: i64,
     pub rate: i64,
     pub model: u64,
+    // Trait index for model 1, lock duration index for model 3
+    pub tier: u64,
+    // GlobalPool penalty_index when redistributed forfeits were last credited
+    pub penalty_index: u64,
+    // 1 renews an expired lock at the same tier until the owner opts out
+    pub auto_relock: u64,
+    // When the unbonding cooldown ends, 0 while staked. Unbonding NFTs no longer accrue.
+    pub unbonding_end: i64,
+}
+
+#[zero_copy]
+#[derive(Default, PartialEq)]
+pub struct VestingTranche {
+    pub amount: u64,
+    pub released: u64,
+    pub start_time: i64,
+}
+
+#[zero_copy]
+#[derive(Default, PartialEq)]
+pub struct Beneficiary {
+    pub wallet: Pubkey,
+    pub share_bps: u64,
+}
+
+#[zero_copy]
+#[derive(Default, PartialEq)]
+pub struct CampaignShare {
+    pub campaign: Pubkey,
+    // CampaignPool index at the last checkpoint
+    pub index: u64,
+    pub pending: u64,
 }
 
 #[account(zero_copy)]
 pub struct UserPool {
-    // 12064
+    // 16384
     pub owner: Pubkey,                           // 32
     pub item_count: u64,                         // 8
-    pub items: [StakedNFT; NFT_STAKE_MAX_COUNT], // (72 + 8) * 150 = 12000
+    pub items: [StakedNFT; NFT_STAKE_MAX_COUNT], // (72 + 32) * 150 = 15600
     pub reward_time: i64,                        // 8
     pub pending_reward: u64,                     // 8
+    pub reward_debt: u64,                        // 8
+    pub stream_reward_time: [i64; MAX_REWARD_STREAMS], // 24
+    pub stream_pending: [u64; MAX_REWARD_STREAMS],     // 24
+    pub vesting: [VestingTranche; MAX_VESTING_TRANCHES], // 24 * 8 = 192
+    // Claims are split between these wallets, the owner keeps what is left
+    pub beneficiary_count: u64,                              // 8
+    pub beneficiaries: [Beneficiary; MAX_BENEFICIARIES],     // 40 * 4 = 160
+    // Hot wallet allowed to claim, and to stake when delegate_can_stake is 1. Rewards
+    // and unstaked NFTs always go to the owner.
+    pub delegate: Pubkey,                                    // 32
+    pub delegate_can_stake: u64,                             // 8
+    // Holding tier bonus accrued up to holding_time, checkpointed whenever the staked count changes
+    pub holding_time: i64,                                   // 8
+    pub holding_pending: u64,                                // 8
+    // Staked model 1 NFTs per trait, and the set bonus accrued up to set_time
+    pub trait_counts: [u64; MAX_TRAITS],                     // 40
+    pub set_time: i64,                                       // 8
+    pub set_pending: u64,                                    // 8
+    // Campaign rewards by GlobalPool campaign slot
+    pub campaign_shares: [CampaignShare; MAX_CAMPAIGNS],     // 48 * 4 = 192
+    // Early unstake penalty the pending reward did not cover, taken from later claims
+    pub penalty_due: u64,                                    // 8
 }
 
 impl Default for UserPool {
@@ -55,34 +891,246 @@ impl Default for UserPool {
           }; NFT_STAKE_MAX_COUNT],
           reward_time: 0,
           pending_reward: 0,
+          reward_debt: 0,
+          stream_reward_time: [0; MAX_REWARD_STREAMS],
+          stream_pending: [0; MAX_REWARD_STREAMS],
+          vesting: [VestingTranche {
+              ..Default::default()
+          }; MAX_VESTING_TRANCHES],
+          beneficiary_count: 0,
+          beneficiaries: [Beneficiary {
+              ..Default::default()
+          }; MAX_BENEFICIARIES],
+          delegate: Pubkey::default(),
+          delegate_can_stake: 0,
+          holding_time: 0,
+          holding_pending: 0,
+          trait_counts: [0; MAX_TRAITS],
+          set_time: 0,
+          set_pending: 0,
+          campaign_shares: [CampaignShare {
+              ..Default::default()
+          }; MAX_CAMPAIGNS],
+          penalty_due: 0,
       }
   }
 }
 
 impl UserPool {
+    pub fn accrued(&self, index: usize, global: &GlobalPool, now: i64) -> u64 {
+        let item = &self.items[index];
+        if item.unbonding_end != 0 {
+            return 0;
+        }
+        let mut last_reward_time = item.reward_time;
+        if last_reward_time < item.stake_time {
+            last_reward_time = item.stake_time;
+        }
+        global.accrual(item, item.rate, true, last_reward_time, now)
+            + global.penalty_index.saturating_sub(item.penalty_index)
+    }
+
+    fn stream_accrued(&self, index: usize, global: &GlobalPool, stream_index: usize, now: i64) -> u64 {
+        let item = &self.items[index];
+        if item.unbonding_end != 0 {
+            return 0;
+        }
+        let stream = &global.reward_streams[stream_index];
+        let last_reward_time = (item.stake_time + global.warmup_period)
+            .max(self.stream_reward_time[stream_index])
+            .max(stream.start_time);
+        // Piecewise over the terms in force, replaced terms still pay until replaced_at
+        let mut points: Vec<i64> = stream
+            .past_terms
+            .iter()
+            .map(|past| past.replaced_at)
+            .filter(|point| *point > last_reward_time && *point < now)
+            .collect();
+        points.push(last_reward_time);
+        points.push(now);
+        points.sort_unstable();
+        let mut reward = 0;
+        for segment in points.windows(2) {
+            let (start, end) = (segment[0], segment[1]);
+            let (rate, end_time) = stream.terms_at(item, start);
+            let end = if end_time == 0 { end } else { end.min(end_time) };
+            reward += global.accrual(item, rate as i64, false, start, end);
+        }
+        reward
+    }
+
+    // Staked items the wallet bonuses count, sorted by the end of their warmup from which
+    // they start counting
+    fn bonus_items(&self, global: &GlobalPool) -> Vec<(i64, &StakedNFT)> {
+        let mut items: Vec<(i64, &StakedNFT)> = self.items[..self.item_count as usize]
+            .iter()
+            .filter(|item| item.unbonding_end == 0)
+            .map(|item| (item.stake_time + global.warmup_period, item))
+            .collect();
+        items.sort_unstable_by_key(|(warmup_end, _)| *warmup_end);
+        items
+    }
+
+    // Splits from..to where the counted items or the bonus config can change and merges
+    // neighbouring segments that earn the same bonus. bonus_at is called once per segment
+    // start in ascending order, so it can keep running counts. Returns (start, end, bonus).
+    fn bonus_runs<T: Copy + PartialEq>(
+        items: &[(i64, &StakedNFT)],
+        config_ends: impl Iterator<Item = i64>,
+        from: i64,
+        to: i64,
+        mut bonus_at: impl FnMut(i64) -> T,
+    ) -> Vec<(i64, i64, T)> {
+        let mut points: Vec<i64> = items
+            .iter()
+            .map(|(warmup_end, _)| *warmup_end)
+            .chain(config_ends)
+            .filter(|point| *point > from && *point < to)
+            .collect();
+        points.push(to);
+        points.sort_unstable();
+        points.dedup();
+        let mut runs: Vec<(i64, i64, T)> = vec![];
+        let mut start = from;
+        for end in points {
+            let bonus = bonus_at(start);
+            match runs.last_mut() {
+                Some(run) if run.2 == bonus => run.1 = end,
+                _ => runs.push((start, end, bonus)),
+            }
+            start = end;
+        }
+        runs
+    }
+
+    // Reward the counted items earn over start..end, each from the end of its warmup on
+    fn bonus_accrual(global: &GlobalPool, items: &[(i64, &StakedNFT)], start: i64, end: i64) -> u64 {
+        items
+            .iter()
+            .take_while(|(warmup_end, _)| *warmup_end < end)
+            .map(|(warmup_end, item)| global.accrual(item, item.rate, true, start.max(*warmup_end), end))
+            .sum()
+    }
+
+    // Credits the holding tier bonus earned since holding_time, piecewise over the counted
+    // items and the tiers in force. Must run before anything changes the staked items.
+    pub fn checkpoint_holding(&mut self, global: &GlobalPool, now: i64) {
+        if self.holding_time > 0 && now > self.holding_time {
+            let items = self.bonus_items(global);
+            let config_ends = global.past_holding_tiers.iter().map(|past| past.end_time);
+            let mut counted = 0;
+            let runs = Self::bonus_runs(&items, config_ends, self.holding_time, now, |at| {
+                while counted < items.len() && items[counted].0 <= at {
+                    counted += 1;
+                }
+                global.holding_bonus(counted as u64, at)
+            });
+            let mut total: u128 = 0;
+            for (start, end, bonus_bps) in runs {
+                if bonus_bps > 0 {
+                    total += Self::bonus_accrual(global, &items, start, end) as u128 * bonus_bps as u128;
+                }
+            }
+            self.holding_pending += (total / BPS_DENOMINATOR as u128) as u64;
+        }
+        self.holding_time = now;
+    }
+
+    // Credits the trait set bonus for the time since set_time if the set was complete.
+    // Must run before anything changes trait_counts or item rates.
+    pub fn checkpoint_set(&mut self, global: &GlobalPool, now: i64) {
+        if self.set_time > 0 && now > self.set_time {
+            let items = self.bonus_items(global);
+            let config_ends = global.past_set_bonuses.iter().map(|past| past.end_time);
+            let mut counted = 0;
+            let mut counts = [0; MAX_TRAITS];
+            let runs = Self::bonus_runs(&items, config_ends, self.set_time, now, |at| {
+                while counted < items.len() && items[counted].0 <= at {
+                    let item = items[counted].1;
+                    if item.model == 1 && (item.tier as usize) < MAX_TRAITS {
+                        counts[item.tier as usize] += 1;
+                    }
+                    counted += 1;
+                }
+                // trait_counts includes NFTs in warmup, only recount when it completes the set
+                if !global.is_set_complete(&self.trait_counts, at) || !global.is_set_complete(&counts, at) {
+                    return (0, 0);
+                }
+                let (_, set_bonus_bps, set_bonus_daily) = global.set_bonus_at(at);
+                (set_bonus_bps, set_bonus_daily)
+            });
+            let mut bps_total: u128 = 0;
+            let mut daily_total: u128 = 0;
+            for (start, end, (set_bonus_bps, set_bonus_daily)) in runs {
+                if set_bonus_bps > 0 {
+                    bps_total += Self::bonus_accrual(global, &items, start, end) as u128 * set_bonus_bps as u128;
+                }
+                daily_total += set_bonus_daily as u128 * global.active_seconds(start, end) as u128;
+            }
+            self.set_pending += (bps_total / BPS_DENOMINATOR as u128 + daily_total / DAY as u128) as u64;
+        }
+        self.set_time = now;
+    }
+
+    // Credits every campaign's reward since the last checkpoint. Must run after
+    // GlobalPool::advance_campaigns and before anything changes the staked items.
+    pub fn checkpoint_campaigns(&mut self, global: &GlobalPool) {
+        for (slot, pool) in global.campaigns.iter().enumerate() {
+            if pool.campaign == Pubkey::default() {
+                continue;
+            }
+            let eligible = (0..self.item_count as usize)
+                .filter(|index| self.items[*index].unbonding_end == 0 && pool.is_eligible(&self.items[*index]))
+                .count() as u64;
+            let share = &mut self.campaign_shares[slot];
+            if share.campaign != pool.campaign {
+                // No checkpoint since the campaign took the slot, so the staked items are
+                // unchanged since it started at index 0
+                *share = CampaignShare { campaign: pool.campaign, index: 0, pending: 0 };
+            }
+            share.pending += eligible * (pool.index - share.index);
+            share.index = pool.index;
+        }
+    }
+
+    pub fn checkpoint_bonuses(&mut self, global: &GlobalPool, now: i64) {
+        self.checkpoint_holding(global, now);
+        self.checkpoint_set(global, now);
+        self.checkpoint_campaigns(global);
+    }
+
+    fn count_trait(&mut self, item: &StakedNFT, staked: bool) {
+        if item.model != 1 || item.tier as usize >= MAX_TRAITS {
+            return;
+        }
+        let count = &mut self.trait_counts[item.tier as usize];
+        if staked {
+            *count += 1;
+        } else {
+            *count = count.saturating_sub(1);
+        }
+    }
+
     pub fn add_nft(&mut self, item: StakedNFT) {
         self.items[self.item_count as usize] = item;
         self.item_count += 1;
+        self.count_trait(&item, true);
     }
-    pub fn remove_nft(&mut self, owner: Pubkey, nft_mint: Pubkey, now: i64) -> Result<u64> {
+    pub fn remove_nft(&mut self, owner: Pubkey, nft_mint: Pubkey, global: &GlobalPool, now: i64) -> Result<(u64, StakedNFT)> {
         require!(self.owner.eq(&owner), StakingError::InvalidOwner);
+        self.checkpoint_bonuses(global, now);
         let mut withdrawn: u8 = 0;
         let mut reward: u64 = 0;
+        let mut item = StakedNFT::default();
         for i in 0..self.item_count {
             let index = i as usize;
             if self.items[index].nft_addr.eq(&nft_mint) {
-                if self.items[index].model == 3 {
-                    require!(
-                        self.items[index].lock_time < now,
-                        StakingError::BeforeLockTime
-                    );
+                self.check_unstake(index, global, now)?;
+                reward = self.accrued(index, global, now);
+                item = self.items[index];
+                for stream_index in 0..global.reward_streams.len() {
+                    self.stream_pending[stream_index] += self.stream_accrued(index, global, stream_index, now);
                 }
-                let mut last_reward_time = self.reward_time;
-                if last_reward_time < self.items[index].stake_time {
-                    last_reward_time = self.items[index].stake_time;
-                }
-
-                reward = (self.items[index].rate * (now - last_reward_time) / DAY) as u64;
 
                 // remove nft
                 if i != self.item_count - 1 {
@@ -95,41 +1143,1190 @@ impl UserPool {
             }
         }
         require!(withdrawn == 1, StakingError::InvalidNFTAddress);
-        Ok(reward)
+        self.count_trait(&item, false);
+        Ok((reward, item))
+    }
+    // Drops the stake without paying rewards or checkpointing anything, so whatever the
+    // item earned since the last checkpoint is forfeited
+    pub fn force_remove_nft(&mut self, owner: Pubkey, nft_mint: Pubkey) -> Result<StakedNFT> {
+        require!(self.owner.eq(&owner), StakingError::InvalidOwner);
+        let index = self.item_index(&nft_mint)?;
+        let item = self.items[index];
+        let last_idx = (self.item_count - 1) as usize;
+        self.items[index] = self.items[last_idx];
+        self.item_count -= 1;
+        // Unbonding NFTs were uncounted when unbonding started
+        if item.unbonding_end == 0 {
+            self.count_trait(&item, false);
+        }
+        Ok(item)
     }
-    pub fn claim_reward(&mut self, owner: Pubkey, nft_mint: Pubkey, now: i64) -> Result<u64> {
+    pub fn claim_reward(&mut self, owner: Pubkey, nft_mint: Pubkey, global: &GlobalPool, now: i64) -> Result<u64> {
         require!(self.owner.eq(&owner), StakingError::InvalidOwner);
         let mut reward: u64 = 0;
         for i in 0..self.item_count {
             let index = i as usize;
             if self.items[index].nft_addr.eq(&nft_mint) {
-                let mut last_reward_time = self.items[index].reward_time;
-                if last_reward_time < self.items[index].stake_time {
-                    last_reward_time = self.items[index].stake_time;
-                }
-                
-                reward = (self.items[index].rate * (now - last_reward_time) / DAY) as u64;
+                require!(!global.in_warmup(&self.items[index], now), StakingError::InWarmup);
+                reward = self.accrued(index, global, now);
                 self.items[index].reward_time = now;
+                self.items[index].penalty_index = global.penalty_index;
             }
         }
         Ok(reward)
     }
 
-    pub fn claim_reward_all(&mut self, now: i64) -> Result<u64> {
-        let mut total_reward: u64 = 0;
+    pub fn claim_reward_all(&mut self, global: &GlobalPool, now: i64) -> Result<u64> {
+        self.checkpoint_bonuses(global, now);
+        let mut total_reward: u64 = self.holding_pending + self.set_pending;
+        self.holding_pending = 0;
+        self.set_pending = 0;
         for i in 0..self.item_count {
             let index = i as usize;
-            let mut last_reward_time = self.items[index].reward_time;
-            if last_reward_time < self.items[index].stake_time {
-                last_reward_time = self.items[index].stake_time;
+            // Warming up NFTs keep accruing until they can be claimed
+            if global.in_warmup(&self.items[index], now) {
+                continue;
             }
-            let reward = (self.items[index].rate * (now - last_reward_time) / DAY) as u64;
+            let reward = self.accrued(index, global, now);
             total_reward += reward;
             self.items[index].reward_time = now; //Super added this
+            self.items[index].penalty_index = global.penalty_index;
         }
         total_reward += self.pending_reward;
         self.pending_reward = 0;
         self.reward_time = now;
         Ok(total_reward)
     }
+
+    fn check_unstake(&mut self, index: usize, global: &GlobalPool, now: i64) -> Result<()> {
+        require!(self.items[index].unbonding_end == 0, StakingError::Unbonding);
+        if self.items[index].model == 3 {
+            self.items[index].lock_time = global.current_lock_time(&self.items[index], now);
+            require!(
+                self.items[index].lock_time < now || global.early_unstake,
+                StakingError::BeforeLockTime
+            );
+        }
+        Ok(())
+    }
+
+    // Settles rewards and starts the unbonding cooldown, the NFT stays in items until
+    // complete_unbonding
+    pub fn start_unbonding(&mut self, owner: Pubkey, nft_mint: Pubkey, global: &GlobalPool, now: i64) -> Result<(u64, StakedNFT)> {
+        require!(self.owner.eq(&owner), StakingError::InvalidOwner);
+        let index = self.item_index(&nft_mint)?;
+        self.check_unstake(index, global, now)?;
+        self.checkpoint_bonuses(global, now);
+        self.checkpoint_streams(global, now);
+        let reward = self.accrued(index, global, now);
+        let item = self.items[index];
+        self.items[index].reward_time = now;
+        self.items[index].unbonding_end = now + global.unbonding_period;
+        self.count_trait(&item, false);
+        Ok((reward, item))
+    }
+
+    pub fn complete_unbonding(&mut self, owner: Pubkey, nft_mint: Pubkey, now: i64) -> Result<StakedNFT> {
+        require!(self.owner.eq(&owner), StakingError::InvalidOwner);
+        let index = self.item_index(&nft_mint)?;
+        let unbonding_end = self.items[index].unbonding_end;
+        require!(unbonding_end != 0 && unbonding_end <= now, StakingError::BeforeUnbondingEnd);
+        self.force_remove_nft(owner, nft_mint)
+    }
+
+    fn item_index(&self, nft_mint: &Pubkey) -> Result<usize> {
+        (0..self.item_count as usize)
+            .find(|index| self.items[*index].nft_addr.eq(nft_mint))
+            .ok_or_else(|| StakingError::InvalidNFTAddress.into())
+    }
+
+    // Moves every stream's accrual into stream_pending so item rates can change
+    pub fn checkpoint_streams(&mut self, global: &GlobalPool, now: i64) {
+        for stream_index in 0..global.reward_streams.len() {
+            for i in 0..self.item_count {
+                self.stream_pending[stream_index] += self.stream_accrued(i as usize, global, stream_index, now);
+            }
+            self.stream_reward_time[stream_index] = now;
+        }
+    }
+
+    // Settles accrued rewards and moves a lock tier NFT to a tier at least as long,
+    // returning the item before and after
+    pub fn extend_lock(&mut self, global: &GlobalPool, nft_mint: Pubkey, lock_period: u8, now: i64) -> Result<(StakedNFT, StakedNFT)> {
+        let index = self.item_index(&nft_mint)?;
+        let item = self.items[index];
+        require!(item.unbonding_end == 0, StakingError::Unbonding);
+        require!(!global.in_warmup(&item, now), StakingError::InWarmup);
+        require!(item.model == 3, StakingError::InvalidLockPeriod);
+        let tier = global
+            .lock_durations
+            .iter()
+            .position(|x| *x == lock_period)
+            .ok_or(StakingError::InvalidLockPeriod)?;
+        let lock_time = now + DAY * lock_period as i64;
+        require!(
+            global.lock_duration(&item) <= lock_period as i64 * DAY
+                && lock_time >= global.current_lock_time(&item, now),
+            StakingError::InvalidLockPeriod
+        );
+
+        self.checkpoint_bonuses(global, now);
+        self.checkpoint_streams(global, now);
+        self.pending_reward += self.accrued(index, global, now);
+        let extended = &mut self.items[index];
+        extended.reward_time = now;
+        extended.penalty_index = global.penalty_index;
+        extended.tier = tier as u64;
+        extended.rate = global.lock_rates[tier] as i64;
+        extended.lock_time = lock_time;
+        Ok((item, *extended))
+    }
+
+    pub fn set_auto_relock(&mut self, global: &GlobalPool, nft_mint: Pubkey, enabled: bool, now: i64) -> Result<()> {
+        let index = self.item_index(&nft_mint)?;
+        let item = self.items[index];
+        require!(item.unbonding_end == 0, StakingError::Unbonding);
+        require!(!global.in_warmup(&item, now), StakingError::InWarmup);
+        require!(item.model == 3, StakingError::InvalidLockPeriod);
+
+        // Auto-relock changes how the rate decays after lock_time, settle what accrued so far
+        self.checkpoint_bonuses(global, now);
+        self.checkpoint_streams(global, now);
+        self.pending_reward += self.accrued(index, global, now);
+        let item = &mut self.items[index];
+        item.reward_time = now;
+        item.penalty_index = global.penalty_index;
+        // Opting out keeps the commitment of the period already running
+        item.lock_time = global.current_lock_time(item, now);
+        item.auto_relock = enabled as u64;
+        Ok(())
+    }
+
+    pub fn claim_stream(&mut self, global: &GlobalPool, stream_index: usize, now: i64) -> Result<u64> {
+        require!(stream_index < global.reward_streams.len(), StakingError::InvalidRewardStream);
+        let mut total_reward: u64 = 0;
+        for i in 0..self.item_count {
+            total_reward += self.stream_accrued(i as usize, global, stream_index, now);
+        }
+        total_reward += self.stream_pending[stream_index];
+        self.stream_pending[stream_index] = 0;
+        self.stream_reward_time[stream_index] = now;
+        Ok(total_reward)
+    }
+
+    pub fn claim_campaign(&mut self, global: &GlobalPool, campaign: &Pubkey) -> Result<u64> {
+        let slot = global.campaign_slot(campaign)?;
+        self.checkpoint_campaigns(global);
+        let share = &mut self.campaign_shares[slot];
+        let reward = share.pending;
+        share.pending = 0;
+        Ok(reward)
+    }
+
+    // Adds outstanding debt to reward and returns the amount payable right now,
+    // recording any shortfall as debt when partial payouts are enabled
+    pub fn settle_reward(&mut self, global: &mut GlobalPool, reward: u64, vault_amount: u64) -> Result<u64> {
+        // Early unstake penalties the pending reward did not cover come out of the reward first
+        let charged = reward.min(self.penalty_due);
+        self.penalty_due -= charged;
+        let reward = reward - charged;
+        let available = global.available_rewards(vault_amount);
+        let owed = reward + self.reward_debt;
+        global.total_accrued = global.total_accrued.saturating_sub(reward);
+        let paid = if owed <= available {
+            global.total_debt -= self.reward_debt;
+            self.reward_debt = 0;
+            owed
+        } else {
+            if !global.partial_payouts {
+                if global.reward_source == REWARD_SOURCE_MINT {
+                    return Err(StakingError::MintCapExceeded.into());
+                }
+                return Err(StakingError::LackLamports.into());
+            }
+            let debt = owed - available;
+            global.total_debt = global.total_debt - self.reward_debt + debt;
+            self.reward_debt = debt;
+            available
+        };
+        global.total_distributed += paid;
+        if global.reward_source == REWARD_SOURCE_MINT {
+            global.total_minted += paid;
+        }
+        Ok(paid)
+    }
+
+    // Charges an early unstake penalty from the pending reward, the rest from later claims
+    pub fn charge_penalty(&mut self, penalty: u64) {
+        let charged = penalty.min(self.pending_reward);
+        self.pending_reward -= charged;
+        self.penalty_due += penalty - charged;
+    }
+
+    // Locks a claimed reward into a new vesting tranche. Once every tranche is in use the
+    // reward is merged into the newest one at the amount weighted start time.
+    pub fn add_vesting(&mut self, amount: u64, now: i64) {
+        if amount == 0 {
+            return;
+        }
+        if let Some(tranche) = self.vesting.iter_mut().find(|tranche| tranche.amount == 0) {
+            *tranche = VestingTranche { amount, released: 0, start_time: now };
+            return;
+        }
+        let newest = self.vesting.iter_mut().max_by_key(|tranche| tranche.start_time).unwrap();
+        let locked = newest.amount - newest.released;
+        newest.start_time = ((newest.start_time as i128 * locked as i128 + now as i128 * amount as i128)
+            / (locked + amount) as i128) as i64;
+        newest.amount += amount;
+    }
+
+    // Marks everything unlocked so far as released and frees finished tranches
+    pub fn release_vested(&mut self, global: &GlobalPool, now: i64) -> u64 {
+        let mut total_released: u64 = 0;
+        for tranche in self.vesting.iter_mut() {
+            if tranche.amount == 0 {
+                continue;
+            }
+            // A merge or schedule change never takes back what was already released
+            let vested = global
+                .vested_amount(tranche.amount, tranche.start_time, now)
+                .max(tranche.released);
+            total_released += vested - tranche.released;
+            tranche.released = vested;
+            if tranche.released == tranche.amount {
+                *tranche = VestingTranche::default();
+            }
+        }
+        total_released
+    }
+
+    // The delegate is a hot wallet acting for the owner. Whatever it claims is still paid
+    // to the owner and the beneficiaries, so a leaked delegate key cannot redirect rewards.
+    pub fn delegate_allows(&self, key: &Pubkey, access: u8) -> bool {
+        if self.delegate == Pubkey::default() || !self.delegate.eq(key) {
+            return false;
+        }
+        match access {
+            ACCESS_CLAIM => true,
+            ACCESS_STAKE => self.delegate_can_stake == 1,
+            _ => false,
+        }
+    }
+
+    pub fn set_beneficiaries(&mut self, wallets: &[Pubkey], shares: &[u16]) -> Result<()> {
+        require!(
+            wallets.len() == shares.len() && wallets.len() <= MAX_BENEFICIARIES,
+            StakingError::InvalidBeneficiaries
+        );
+        let total_share: u64 = shares.iter().map(|share| *share as u64).sum();
+        require!(total_share <= BPS_DENOMINATOR, StakingError::InvalidBeneficiaries);
+        self.beneficiaries = [Beneficiary::default(); MAX_BENEFICIARIES];
+        for (index, (wallet, share)) in wallets.iter().zip(shares.iter()).enumerate() {
+            require!(
+                *wallet != Pubkey::default() && *share > 0,
+                StakingError::InvalidBeneficiaries
+            );
+            self.beneficiaries[index] = Beneficiary { wallet: *wallet, share_bps: *share as u64 };
+        }
+        self.beneficiary_count = wallets.len() as u64;
+        Ok(())
+    }
+
+    pub fn beneficiary_share(&self, index: usize, reward: u64) -> u64 {
+        (reward as u128 * self.beneficiaries[index].share_bps as u128 / BPS_DENOMINATOR as u128) as u64
+    }
+
+    pub fn unreleased_vesting(&self) -> u64 {
+        self.vesting.iter().map(|tranche| tranche.amount - tranche.released).sum()
+    }
+
+    // Nothing staked, owed or owing, so closing the pool loses nothing
+    pub fn is_empty(&self) -> bool {
+        self.item_count == 0
+            && self.pending_reward == 0
+            && self.reward_debt == 0
+            && self.penalty_due == 0
+            && self.holding_pending == 0
+            && self.set_pending == 0
+            && self.stream_pending.iter().all(|pending| *pending == 0)
+            && self.campaign_shares.iter().all(|share| share.pending == 0)
+            && self.unreleased_vesting() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn admin_holds_every_role() {
+        let admin = Pubkey::new_unique();
+        let global = GlobalPool { admin, ..Default::default() };
+        for role in [ROLE_CONFIG_MANAGER, ROLE_TREASURER, ROLE_PAUSER, ROLE_TRAIT_ORACLE] {
+            assert!(global.has_role(role, &admin).unwrap());
+        }
+    }
+
+    #[test]
+    fn roles_are_held_separately_and_revocable() {
+        let mut global = GlobalPool { admin: Pubkey::new_unique(), ..Default::default() };
+        let pauser = Pubkey::new_unique();
+        global.set_role(ROLE_PAUSER, pauser).unwrap();
+        assert_eq!(global.role_holder(ROLE_PAUSER).unwrap(), pauser);
+        assert!(global.has_role(ROLE_PAUSER, &pauser).unwrap());
+        assert!(!global.has_role(ROLE_TREASURER, &pauser).unwrap());
+        global.set_role(ROLE_PAUSER, Pubkey::default()).unwrap();
+        assert!(!global.has_role(ROLE_PAUSER, &pauser).unwrap());
+        // An unassigned role is never held by the default key
+        assert!(!global.has_role(ROLE_PAUSER, &Pubkey::default()).unwrap());
+    }
+
+    #[test]
+    fn unknown_roles_are_rejected() {
+        let mut global = GlobalPool::default();
+        assert!(global.set_role(4, Pubkey::new_unique()).is_err());
+        assert!(global.has_role(4, &Pubkey::new_unique()).is_err());
+    }
+
+    #[test]
+    fn pause_flags_block_only_their_operation() {
+        let mut global = GlobalPool::default();
+        global.pause(PAUSE_STAKE | PAUSE_WITHDRAW, 0);
+        assert!(global.check_not_paused(PAUSE_STAKE).is_err());
+        assert!(global.check_not_paused(PAUSE_WITHDRAW).is_err());
+        assert!(global.check_not_paused(PAUSE_CLAIM).is_ok());
+        global.unpause(PAUSE_STAKE, 10);
+        assert!(global.check_not_paused(PAUSE_STAKE).is_ok());
+        assert!(global.check_not_paused(PAUSE_WITHDRAW).is_err());
+    }
+
+    #[test]
+    fn claim_pauses_are_excluded_from_accrual_when_enabled() {
+        let mut global = GlobalPool { exclude_paused_time: true, ..Default::default() };
+        global.pause(PAUSE_CLAIM, 100);
+        // Still open, paused until to
+        assert_eq!(global.active_seconds(0, 300), 100);
+        global.unpause(PAUSE_CLAIM, 200);
+        assert_eq!(global.active_seconds(0, 300), 200);
+        assert_eq!(global.active_seconds(150, 250), 50);
+
+        let mut global = GlobalPool::default();
+        global.pause(PAUSE_CLAIM, 100);
+        global.unpause(PAUSE_CLAIM, 200);
+        assert_eq!(global.active_seconds(0, 300), 300);
+    }
+
+    #[test]
+    fn paused_time_is_excluded_from_accrual() {
+        let mut global = GlobalPool { exclude_paused_time: true, ..Default::default() };
+        global.pause(PAUSE_STAKE, 100);
+        global.pause(PAUSE_CLAIM, 150);
+        global.unpause(PAUSE_STAKE, 200);
+        // Still paused for claims, the window stays open
+        assert_eq!(global.active_seconds(0, 300), 100);
+        global.unpause(PAUSE_CLAIM, 250);
+        assert_eq!(global.pause_windows.len(), 1);
+        assert_eq!(global.active_seconds(0, 300), 150);
+        assert_eq!(global.active_seconds(120, 260), 10);
+    }
+
+    #[test]
+    fn withdraw_pause_keeps_accruing() {
+        let mut global = GlobalPool { exclude_paused_time: true, ..Default::default() };
+        global.pause(PAUSE_WITHDRAW, 100);
+        assert!(global.pause_windows.is_empty());
+        assert_eq!(global.active_seconds(0, 300), 300);
+    }
+
+    #[test]
+    fn full_pause_buffer_merges_the_closest_windows() {
+        let mut global = GlobalPool { exclude_paused_time: true, ..Default::default() };
+        for i in 0..MAX_PAUSE_WINDOWS as i64 {
+            global.pause(PAUSE_CLAIM, i * 100);
+            global.unpause(PAUSE_CLAIM, i * 100 + 10);
+        }
+        // Windows 90 apart, the first two are merged
+        global.pause(PAUSE_CLAIM, 1_000);
+        assert_eq!(global.paused, PAUSE_CLAIM);
+        assert_eq!(global.pause_windows.len(), MAX_PAUSE_WINDOWS);
+        assert_eq!(global.active_seconds(0, 1_000), 1_000 - 10 * MAX_PAUSE_WINDOWS as i64 - 90);
+        assert_eq!(global.active_seconds(1_000, 1_100), 0);
+        global.unpause(PAUSE_CLAIM, 1_010);
+
+        // Paused again right away, the last window is reopened
+        global.pause(PAUSE_CLAIM, 1_015);
+        assert_eq!(global.pause_windows.len(), MAX_PAUSE_WINDOWS);
+        assert_eq!(global.active_seconds(1_000, 1_100), 0);
+        assert_eq!(global.pause_windows.last().unwrap().start, 1_000);
+    }
+
+    #[test]
+    fn force_remove_drops_the_stake_without_rewards() {
+        let owner = Pubkey::new_unique();
+        let mut pool = UserPool { owner, ..Default::default() };
+        for mint in 1..=3u8 {
+            pool.add_nft(StakedNFT {
+                nft_addr: Pubkey::new_from_array([mint; 32]),
+                reward_time: mint as i64 * 10,
+                ..Default::default()
+            });
+        }
+        let item = pool.force_remove_nft(owner, Pubkey::new_from_array([1; 32])).unwrap();
+        assert_eq!(item.reward_time, 10);
+        assert_eq!(pool.item_count, 2);
+        // The last item takes the removed slot
+        assert_eq!(pool.items[0].nft_addr, Pubkey::new_from_array([3; 32]));
+        assert!(pool.force_remove_nft(owner, Pubkey::new_from_array([1; 32])).is_err());
+        assert!(pool.force_remove_nft(Pubkey::new_unique(), Pubkey::new_from_array([2; 32])).is_err());
+        assert_eq!(pool.item_count, 2);
+    }
+
+    #[test]
+    fn pools_with_anything_pending_are_not_empty() {
+        assert!(UserPool::default().is_empty());
+        let mut pool = UserPool { penalty_due: 1, ..Default::default() };
+        assert!(!pool.is_empty());
+        pool = UserPool { set_pending: 1, ..Default::default() };
+        assert!(!pool.is_empty());
+        pool = UserPool::default();
+        pool.stream_pending[2] = 1;
+        assert!(!pool.is_empty());
+        pool = UserPool::default();
+        pool.campaign_shares[1].pending = 1;
+        assert!(!pool.is_empty());
+        pool = UserPool::default();
+        pool.vesting[0] = VestingTranche { amount: 10, released: 10, start_time: 0 };
+        assert!(pool.is_empty());
+        pool.vesting[0].released = 9;
+        assert!(!pool.is_empty());
+    }
+
+    #[test]
+    fn settle_pays_in_full_when_the_vault_covers_it() {
+        let mut global = GlobalPool::default();
+        let mut pool = UserPool::default();
+        assert_eq!(pool.settle_reward(&mut global, 100, 100).unwrap(), 100);
+        assert_eq!(pool.reward_debt, 0);
+        assert_eq!(global.total_debt, 0);
+    }
+
+    #[test]
+    fn settle_fails_on_shortfall_without_partial_payouts() {
+        let mut global = GlobalPool::default();
+        let mut pool = UserPool::default();
+        assert!(pool.settle_reward(&mut global, 100, 60).is_err());
+        assert_eq!(pool.reward_debt, 0);
+    }
+
+    #[test]
+    fn settle_records_the_shortfall_as_debt_and_repays_it_later() {
+        let mut global = GlobalPool { partial_payouts: true, ..Default::default() };
+        let mut pool = UserPool::default();
+        assert_eq!(pool.settle_reward(&mut global, 100, 60).unwrap(), 60);
+        assert_eq!(pool.reward_debt, 40);
+        assert_eq!(global.total_debt, 40);
+        // Still short, the debt is replaced rather than added twice
+        assert_eq!(pool.settle_reward(&mut global, 10, 20).unwrap(), 20);
+        assert_eq!(pool.reward_debt, 30);
+        assert_eq!(global.total_debt, 30);
+        assert_eq!(pool.settle_reward(&mut global, 5, 100).unwrap(), 35);
+        assert_eq!(pool.reward_debt, 0);
+        assert_eq!(global.total_debt, 0);
+    }
+
+    #[test]
+    fn liabilities_accrue_at_the_emission_rate() {
+        let mut global = GlobalPool { emission_rate: 100, ..Default::default() };
+        // Nothing accrues before the first checkpoint
+        assert_eq!(global.accrued_liability(DAY), 0);
+        global.accrue_liability(DAY);
+        assert_eq!(global.accrued_liability(DAY + DAY / 2), 50);
+        global.accrue_liability(2 * DAY);
+        global.emission_rate = 300;
+        assert_eq!(global.accrued_liability(3 * DAY), 100 + 300);
+    }
+
+    #[test]
+    fn runway_covers_liabilities_and_debt_first() {
+        let mut global = GlobalPool { emission_rate: 100, total_debt: 200, ..Default::default() };
+        global.accrue_liability(DAY);
+        // 1000 in the vault less 200 of debt lasts 8 days at 100 a day
+        assert_eq!(global.runway(1_000, DAY), 8 * DAY);
+        // Two days later 200 more has accrued
+        assert_eq!(global.runway(1_000, 3 * DAY), 6 * DAY);
+        assert_eq!(global.runway(1_000, 9 * DAY), 0);
+        assert_eq!(global.runway(100, DAY), 0);
+        global.emission_rate = 0;
+        assert_eq!(global.runway(1_000, DAY), i64::MAX);
+    }
+
+    #[test]
+    fn accrued_liability_saturates_instead_of_overflowing() {
+        let mut global = GlobalPool { emission_rate: u64::MAX / 2, ..Default::default() };
+        global.accrue_liability(DAY);
+        assert_eq!(global.accrued_liability(4 * DAY), u64::MAX);
+        assert_eq!(global.runway(u64::MAX, 4 * DAY), 0);
+    }
+
+    #[test]
+    fn reward_deposit_len_matches_its_layout() {
+        let deposit = RewardDeposit { amount: u64::MAX, last_deposit_time: i64::MAX, ..Default::default() };
+        assert_eq!(deposit.try_to_vec().unwrap().len(), RewardDeposit::LEN);
+    }
+
+    #[test]
+    fn mint_mode_pays_up_to_the_cap() {
+        let mut global = GlobalPool { reward_source: REWARD_SOURCE_MINT, mint_cap: 150, ..Default::default() };
+        let mut pool = UserPool::default();
+        // The vault balance is irrelevant when minting
+        assert_eq!(global.available_rewards(0), 150);
+        assert_eq!(pool.settle_reward(&mut global, 100, 0).unwrap(), 100);
+        assert_eq!(global.total_minted, 100);
+        assert!(pool.settle_reward(&mut global, 100, 0).is_err());
+        assert_eq!(global.total_minted, 100);
+    }
+
+    #[test]
+    fn mint_mode_records_debt_past_the_cap_with_partial_payouts() {
+        let mut global = GlobalPool {
+            reward_source: REWARD_SOURCE_MINT,
+            mint_cap: 150,
+            partial_payouts: true,
+            ..Default::default()
+        };
+        let mut pool = UserPool::default();
+        assert_eq!(pool.settle_reward(&mut global, 200, 0).unwrap(), 150);
+        assert_eq!(pool.reward_debt, 50);
+        assert_eq!(global.available_rewards(0), 0);
+    }
+
+    #[test]
+    fn streams_pay_their_own_rates_within_their_window() {
+        let stream = RewardStream { trait_rates: vec![0, 50], normal_rate: 10, start_time: DAY, end_time: 3 * DAY, ..Default::default() };
+        let global = GlobalPool { reward_streams: vec![stream], ..Default::default() };
+        let mut pool = UserPool { owner: Pubkey::new_unique(), ..Default::default() };
+        pool.add_nft(StakedNFT { nft_addr: Pubkey::new_unique(), model: 1, tier: 1, rate: 100, ..Default::default() });
+        pool.add_nft(StakedNFT { nft_addr: Pubkey::new_unique(), model: 2, rate: 100, ..Default::default() });
+        // Only the two days between start and end pay out
+        assert_eq!(pool.claim_stream(&global, 0, 5 * DAY).unwrap(), 2 * 50 + 2 * 10);
+        assert_eq!(pool.claim_stream(&global, 0, 6 * DAY).unwrap(), 0);
+        assert!(pool.claim_stream(&global, 1, 6 * DAY).is_err());
+    }
+
+    #[test]
+    fn stream_term_changes_apply_from_the_change_on() {
+        let stream = RewardStream { normal_rate: 10, end_time: 4 * DAY, ..Default::default() };
+        let mut global = GlobalPool { reward_streams: vec![stream], ..Default::default() };
+        let mut pool = UserPool { owner: Pubkey::new_unique(), ..Default::default() };
+        pool.add_nft(StakedNFT { nft_addr: Pubkey::new_unique(), model: 2, rate: 100, ..Default::default() });
+
+        // Doubled from day 2 and ended at day 3, the first two days keep the old rate
+        global.reward_streams[0].set_terms(vec![], 20, vec![], 3 * DAY, 2 * DAY);
+        assert_eq!(pool.claim_stream(&global, 0, 5 * DAY).unwrap(), 2 * 10 + 20);
+
+        // Reopened at day 6, the days since the end stay unpaid
+        global.reward_streams[0].set_terms(vec![], 20, vec![], 0, 6 * DAY);
+        assert_eq!(pool.claim_stream(&global, 0, 7 * DAY).unwrap(), 20);
+    }
+
+    #[test]
+    fn unstaking_keeps_stream_rewards_pending() {
+        let stream = RewardStream { normal_rate: 10, ..Default::default() };
+        let global = GlobalPool { reward_streams: vec![stream], ..Default::default() };
+        let owner = Pubkey::new_unique();
+        let nft_mint = Pubkey::new_unique();
+        let mut pool = UserPool { owner, ..Default::default() };
+        pool.add_nft(StakedNFT { nft_addr: nft_mint, model: 2, rate: 100, ..Default::default() });
+        pool.remove_nft(owner, nft_mint, &global, 2 * DAY).unwrap();
+        assert_eq!(pool.stream_pending[0], 20);
+        assert_eq!(pool.claim_stream(&global, 0, 3 * DAY).unwrap(), 20);
+    }
+
+    #[test]
+    fn sol_mode_pays_only_through_the_sol_vault() {
+        let mut global = GlobalPool { reward_source: REWARD_SOURCE_SOL, ..Default::default() };
+        assert!(global.check_reward_source(true).is_ok());
+        assert!(global.check_reward_source(false).is_err());
+        // Lamports in the SOL vault back the payout like a token vault balance
+        let mut pool = UserPool::default();
+        assert_eq!(global.available_rewards(70), 70);
+        assert!(pool.settle_reward(&mut global, 100, 70).is_err());
+        global.reward_source = REWARD_SOURCE_VAULT;
+        assert!(global.check_reward_source(true).is_err());
+        assert!(global.check_reward_source(false).is_ok());
+    }
+
+    #[test]
+    fn stake_counts_track_eligible_nfts() {
+        let mut global = GlobalPool { total_amount: 3, ..Default::default() };
+        let trait_item = StakedNFT { model: 1, tier: 2, ..Default::default() };
+        let lock_item = StakedNFT { model: 3, tier: 0, ..Default::default() };
+        global.count_stake(&trait_item, true);
+        global.count_stake(&trait_item, true);
+        global.count_stake(&lock_item, true);
+        global.count_stake(&StakedNFT { model: 2, ..Default::default() }, true);
+        assert_eq!(global.eligible_count(ELIGIBILITY_TRAIT, 2), 2);
+        assert_eq!(global.eligible_count(ELIGIBILITY_TRAIT, 0), 0);
+        assert_eq!(global.eligible_count(ELIGIBILITY_LOCK_TIER, 0), 1);
+        assert_eq!(global.eligible_count(ELIGIBILITY_ALL, 0), 3);
+        global.count_stake(&trait_item, false);
+        assert_eq!(global.eligible_count(ELIGIBILITY_TRAIT, 2), 1);
+    }
+
+    #[test]
+    fn unbonding_stops_accrual_and_holds_the_nft_until_the_cooldown_ends() {
+        let global = GlobalPool { unbonding_period: 2 * DAY, ..Default::default() };
+        let owner = Pubkey::new_unique();
+        let nft_mint = Pubkey::new_unique();
+        let mut pool = UserPool { owner, ..Default::default() };
+        pool.add_nft(StakedNFT { nft_addr: nft_mint, model: 2, rate: 100, ..Default::default() });
+        assert!(pool.start_unbonding(Pubkey::new_unique(), nft_mint, &global, DAY).is_err());
+        let (reward, _) = pool.start_unbonding(owner, nft_mint, &global, DAY).unwrap();
+        assert_eq!(reward, 100);
+        assert_eq!(pool.items[0].unbonding_end, 3 * DAY);
+        // Nothing accrues during the cooldown and it cannot be restarted
+        assert_eq!(pool.accrued(0, &global, 2 * DAY), 0);
+        assert!(pool.start_unbonding(owner, nft_mint, &global, 2 * DAY).is_err());
+        assert!(pool.remove_nft(owner, nft_mint, &global, 2 * DAY).is_err());
+        assert!(pool.complete_unbonding(owner, nft_mint, 3 * DAY - 1).is_err());
+        assert_eq!(pool.complete_unbonding(owner, nft_mint, 3 * DAY).unwrap().nft_addr, nft_mint);
+        assert_eq!(pool.item_count, 0);
+    }
+
+    #[test]
+    fn unbonding_cannot_complete_without_a_request() {
+        let owner = Pubkey::new_unique();
+        let nft_mint = Pubkey::new_unique();
+        let mut pool = UserPool { owner, ..Default::default() };
+        pool.add_nft(StakedNFT { nft_addr: nft_mint, model: 2, ..Default::default() });
+        assert!(pool.complete_unbonding(owner, nft_mint, DAY).is_err());
+        assert_eq!(pool.item_count, 1);
+    }
+
+    #[test]
+    fn warmup_forfeits_rewards_of_early_unstakes() {
+        let mut global = GlobalPool { warmup_period: 2 * DAY, total_accrued: 500, ..Default::default() };
+        let item = StakedNFT { stake_time: DAY, ..Default::default() };
+        assert!(global.in_warmup(&item, 3 * DAY - 1));
+        assert_eq!(global.apply_warmup(&item, 100, 3 * DAY - 1), 0);
+        // The forfeit is no longer owed
+        assert_eq!(global.total_accrued, 400);
+        assert!(!global.in_warmup(&item, 3 * DAY));
+        assert_eq!(global.apply_warmup(&item, 100, 3 * DAY), 100);
+        assert_eq!(global.total_accrued, 400);
+    }
+
+    #[test]
+    fn warming_up_nfts_cannot_claim_yet() {
+        let global = GlobalPool { warmup_period: 2 * DAY, ..Default::default() };
+        let owner = Pubkey::new_unique();
+        let nft_mint = Pubkey::new_unique();
+        let mut pool = UserPool { owner, ..Default::default() };
+        pool.add_nft(StakedNFT { nft_addr: nft_mint, model: 2, rate: 100, ..Default::default() });
+        assert!(pool.claim_reward(owner, nft_mint, &global, DAY).is_err());
+        // claim_reward_all skips it and keeps the reward accruing
+        assert_eq!(pool.claim_reward_all(&global, DAY).unwrap(), 0);
+        assert_eq!(pool.claim_reward(owner, nft_mint, &global, 2 * DAY).unwrap(), 200);
+    }
+
+    fn item(mint: u8, model: u64, tier: u64, stake_time: i64) -> StakedNFT {
+        StakedNFT {
+            nft_addr: Pubkey::new_from_array([mint; 32]),
+            stake_time,
+            reward_time: stake_time,
+            lock_time: stake_time,
+            model,
+            tier,
+            ..Default::default()
+        }
+    }
+
+    fn stake(global: &mut GlobalPool, pool: &mut UserPool, item: StakedNFT) {
+        global.advance_campaigns(item.stake_time);
+        pool.checkpoint_campaigns(global);
+        pool.add_nft(item);
+        global.total_amount += 1;
+        global.count_stake(&item, true);
+    }
+
+    fn campaign_global(eligibility: u8, amount: u64, start_time: i64, end_time: i64) -> GlobalPool {
+        let mut global = GlobalPool::default();
+        global
+            .add_campaign(CampaignPool {
+                campaign: Pubkey::new_from_array([9; 32]),
+                eligibility,
+                eligibility_value: 1,
+                amount,
+                start_time,
+                end_time,
+                last_update: start_time,
+                ..Default::default()
+            })
+            .unwrap();
+        global
+    }
+
+    #[test]
+    fn campaign_is_shared_pro_rata_by_eligible_count() {
+        let campaign = Pubkey::new_from_array([9; 32]);
+        let mut global = campaign_global(ELIGIBILITY_ALL, 1_000, 0, 100);
+        let mut alice = UserPool::default();
+        let mut bob = UserPool::default();
+        stake(&mut global, &mut alice, item(1, 2, 0, 0));
+        // Bob joins halfway and halves Alice's share from then on
+        stake(&mut global, &mut bob, item(2, 2, 0, 50));
+
+        global.advance_campaigns(200);
+        let alice_reward = alice.claim_campaign(&global, &campaign).unwrap();
+        let bob_reward = bob.claim_campaign(&global, &campaign).unwrap();
+        assert_eq!(alice_reward, 750);
+        assert_eq!(bob_reward, 250);
+        assert_eq!(global.campaigns[0].outstanding(alice_reward + bob_reward), 0);
+    }
+
+    #[test]
+    fn campaign_time_without_eligible_nfts_stays_unallocated() {
+        let campaign = Pubkey::new_from_array([9; 32]);
+        let mut global = campaign_global(ELIGIBILITY_TRAIT, 1_000, 0, 100);
+        let mut pool = UserPool::default();
+        // Not eligible, trait 0 instead of trait 1
+        stake(&mut global, &mut pool, item(1, 1, 0, 0));
+        stake(&mut global, &mut pool, item(2, 1, 1, 40));
+
+        global.advance_campaigns(100);
+        assert_eq!(global.campaigns[0].allocated, 600);
+        assert_eq!(pool.claim_campaign(&global, &campaign).unwrap(), 600);
+    }
+
+    #[test]
+    fn ended_campaigns_stop_allocating() {
+        let campaign = Pubkey::new_from_array([9; 32]);
+        let mut global = campaign_global(ELIGIBILITY_ALL, 1_000, 0, 100);
+        let mut pool = UserPool::default();
+        stake(&mut global, &mut pool, item(1, 2, 0, 0));
+
+        assert_eq!(global.end_campaign(0, 40), 40);
+        global.advance_campaigns(100);
+        assert_eq!(global.campaigns[0].allocated, 400);
+        assert_eq!(pool.claim_campaign(&global, &campaign).unwrap(), 400);
+        assert_eq!(global.campaigns[0].outstanding(400), 0);
+
+        // A campaign ended before its start never allocates anything
+        let mut global = campaign_global(ELIGIBILITY_ALL, 1_000, 50, 100);
+        stake(&mut global, &mut UserPool::default(), item(2, 2, 0, 0));
+        assert_eq!(global.end_campaign(0, 10), 10);
+        global.advance_campaigns(100);
+        assert_eq!(global.campaigns[0].allocated, 0);
+    }
+
+    #[test]
+    fn campaign_reward_survives_unstake() {
+        let campaign = Pubkey::new_from_array([9; 32]);
+        let mut global = campaign_global(ELIGIBILITY_ALL, 900, 0, 90);
+        let mut pool = UserPool::default();
+        let staked = item(1, 2, 0, 0);
+        stake(&mut global, &mut pool, staked);
+        stake(&mut global, &mut pool, item(2, 2, 0, 0));
+
+        global.advance_campaigns(30);
+        pool.checkpoint_bonuses(&global, 30);
+        pool.remove_nft(Pubkey::default(), staked.nft_addr, &global, 30).unwrap();
+        global.total_amount -= 1;
+        global.count_stake(&staked, false);
+
+        global.advance_campaigns(90);
+        assert_eq!(pool.claim_campaign(&global, &campaign).unwrap(), 900);
+    }
+
+    #[test]
+    fn campaign_carry_keeps_rounding_remainders() {
+        let campaign = Pubkey::new_from_array([9; 32]);
+        let mut global = campaign_global(ELIGIBILITY_ALL, 100, 0, 100);
+        let mut pools = [UserPool::default(), UserPool::default(), UserPool::default()];
+        for (i, pool) in pools.iter_mut().enumerate() {
+            stake(&mut global, pool, item(i as u8, 2, 0, 0));
+        }
+        for now in 1..=100 {
+            global.advance_campaigns(now);
+        }
+        let claimed: u64 = pools.iter_mut().map(|pool| pool.claim_campaign(&global, &campaign).unwrap()).sum();
+        assert_eq!(claimed, 99);
+        assert_eq!(global.campaigns[0].carry, 1);
+        assert_eq!(global.campaigns[0].outstanding(claimed), 0);
+    }
+
+    #[test]
+    fn campaign_slots_are_reused_once_freed() {
+        let mut global = campaign_global(ELIGIBILITY_ALL, 100, 0, 100);
+        for i in 1..MAX_CAMPAIGNS {
+            global
+                .add_campaign(CampaignPool { campaign: Pubkey::new_from_array([i as u8; 32]), ..Default::default() })
+                .unwrap();
+        }
+        let extra = CampaignPool { campaign: Pubkey::new_unique(), ..Default::default() };
+        assert!(global.add_campaign(extra.clone()).is_err());
+        global.campaigns[1] = CampaignPool::default();
+        global.add_campaign(extra.clone()).unwrap();
+        assert_eq!(global.campaign_slot(&extra.campaign).unwrap(), 1);
+    }
+
+    #[test]
+    fn delegate_can_claim_but_only_stake_when_allowed() {
+        let delegate = Pubkey::new_unique();
+        let mut pool = UserPool { owner: Pubkey::new_unique(), delegate, ..Default::default() };
+        assert!(pool.delegate_allows(&delegate, ACCESS_CLAIM));
+        assert!(!pool.delegate_allows(&delegate, ACCESS_STAKE));
+        assert!(!pool.delegate_allows(&delegate, ACCESS_OWNER));
+        assert!(!pool.delegate_allows(&Pubkey::new_unique(), ACCESS_CLAIM));
+        pool.delegate_can_stake = 1;
+        assert!(pool.delegate_allows(&delegate, ACCESS_STAKE));
+        pool.delegate = Pubkey::default();
+        assert!(!pool.delegate_allows(&Pubkey::default(), ACCESS_CLAIM));
+    }
+
+    fn lock_global() -> GlobalPool {
+        GlobalPool {
+            normal_rate: 10,
+            lock_durations: vec![10],
+            lock_rates: vec![100],
+            post_lock_mode: POST_LOCK_NORMAL,
+            ..Default::default()
+        }
+    }
+
+    fn lock_item(mint: u8) -> StakedNFT {
+        StakedNFT {
+            lock_time: 10 * DAY,
+            rate: 100,
+            ..item(mint, 3, 0, 0)
+        }
+    }
+
+    #[test]
+    fn enabling_auto_relock_keeps_the_decayed_past() {
+        let global = lock_global();
+        let mut pool = UserPool::default();
+        let locked = lock_item(1);
+        pool.add_nft(locked);
+
+        pool.set_auto_relock(&global, locked.nft_addr, true, 20 * DAY).unwrap();
+        // 10 days locked at 100, then 10 days at the normal rate
+        assert_eq!(pool.pending_reward, 1_100);
+        assert_eq!(pool.items[0].reward_time, 20 * DAY);
+        assert_eq!(global.current_lock_time(&pool.items[0], 20 * DAY), 30 * DAY);
+        // The relocked period keeps the lock rate
+        assert_eq!(pool.accrued(0, &global, 25 * DAY), 500);
+    }
+
+    #[test]
+    fn disabling_auto_relock_keeps_the_running_period() {
+        let global = lock_global();
+        let mut pool = UserPool::default();
+        let mut locked = lock_item(1);
+        locked.auto_relock = 1;
+        pool.add_nft(locked);
+
+        pool.set_auto_relock(&global, locked.nft_addr, false, 15 * DAY).unwrap();
+        assert_eq!(pool.pending_reward, 1_500);
+        assert_eq!(pool.items[0].lock_time, 20 * DAY);
+        // Decays once the running period is over
+        assert_eq!(pool.accrued(0, &global, 30 * DAY), 500 + 100);
+    }
+
+    #[test]
+    fn auto_relock_cannot_change_during_warmup() {
+        let mut global = lock_global();
+        global.warmup_period = DAY;
+        let mut pool = UserPool::default();
+        let locked = lock_item(1);
+        pool.add_nft(locked);
+        assert!(pool.set_auto_relock(&global, locked.nft_addr, true, DAY / 2).is_err());
+        assert!(pool.set_auto_relock(&global, locked.nft_addr, true, DAY).is_ok());
+    }
+
+    fn holding_global(bonus_bps: u64) -> GlobalPool {
+        let mut global = GlobalPool::default();
+        global.set_holding_tiers(vec![HoldingTier { min_count: 2, bonus_bps }], 0);
+        global
+    }
+
+    // Stakes an NFT earning 100 per day at each stake time, model 1 NFTs get trait i
+    fn staked_pool(global: &GlobalPool, model: u64, stake_times: &[i64]) -> UserPool {
+        let mut pool = UserPool::default();
+        for (i, stake_time) in stake_times.iter().enumerate() {
+            let tier = if model == 1 { i as u64 } else { 0 };
+            pool.checkpoint_bonuses(global, *stake_time);
+            pool.add_nft(StakedNFT { rate: 100, ..item(i as u8, model, tier, *stake_time) });
+        }
+        pool
+    }
+
+    #[test]
+    fn holding_tier_changes_apply_from_the_change_on() {
+        let mut global = holding_global(1_000);
+        let mut pool = staked_pool(&global, 2, &[DAY, DAY]);
+        global.set_holding_tiers(vec![HoldingTier { min_count: 2, bonus_bps: 2_000 }], 11 * DAY);
+        pool.checkpoint_holding(&global, 21 * DAY);
+        // 10 days at 10% then 10 days at 20% of 200 per day
+        assert_eq!(pool.holding_pending, 200 + 400);
+    }
+
+    #[test]
+    fn holding_history_keeps_every_replaced_config() {
+        let mut global = holding_global(1_000);
+        let mut pool = staked_pool(&global, 2, &[DAY, DAY]);
+        global.set_holding_tiers(vec![HoldingTier { min_count: 2, bonus_bps: 2_000 }], 11 * DAY);
+        global.set_holding_tiers(vec![], 16 * DAY);
+        pool.checkpoint_holding(&global, 21 * DAY);
+        assert_eq!(pool.holding_pending, 200 + 200);
+    }
+
+    #[test]
+    fn holding_tiers_skip_nfts_in_warmup() {
+        let mut global = holding_global(1_000);
+        global.warmup_period = 5 * DAY;
+        let mut pool = staked_pool(&global, 2, &[DAY, 3 * DAY]);
+        pool.checkpoint_holding(&global, 13 * DAY);
+        // Both NFTs only count once the second one is past its warmup at day 8
+        assert_eq!(pool.holding_pending, 2 * 100 * 5 / 10);
+    }
+
+    #[test]
+    fn set_bonus_changes_apply_from_the_change_on() {
+        let mut global = GlobalPool::default();
+        global.set_set_bonus(vec![0, 1], 1_000, 0, 0);
+        let mut pool = staked_pool(&global, 1, &[DAY, DAY]);
+        global.set_set_bonus(vec![0, 1], 2_000, 0, 11 * DAY);
+        pool.checkpoint_set(&global, 21 * DAY);
+        assert_eq!(pool.set_pending, 200 + 400);
+    }
+
+    #[test]
+    fn replaced_trait_set_only_counts_while_in_force() {
+        let mut global = GlobalPool::default();
+        global.set_set_bonus(vec![0, 1], 0, 100, 0);
+        let mut pool = staked_pool(&global, 1, &[DAY, DAY]);
+        global.set_set_bonus(vec![0, 2], 0, 100, 11 * DAY);
+        pool.checkpoint_set(&global, 21 * DAY);
+        assert_eq!(pool.set_pending, 1_000);
+    }
+
+    #[test]
+    fn set_is_complete_once_every_trait_is_past_warmup() {
+        let mut global = GlobalPool { warmup_period: 5 * DAY, ..Default::default() };
+        global.set_set_bonus(vec![0, 1], 0, 100, 0);
+        let mut pool = staked_pool(&global, 1, &[DAY, 3 * DAY]);
+        pool.checkpoint_set(&global, 13 * DAY);
+        assert_eq!(pool.set_pending, 500);
+    }
+
+    fn vesting_global() -> GlobalPool {
+        GlobalPool {
+            vesting_cliff: 10,
+            vesting_duration: 100,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn vested_amount_unlocks_linearly_after_the_cliff() {
+        let global = vesting_global();
+        assert_eq!(global.vested_amount(1_000, 50, 59), 0);
+        assert_eq!(global.vested_amount(1_000, 50, 60), 100);
+        assert_eq!(global.vested_amount(1_000, 50, 125), 750);
+        assert_eq!(global.vested_amount(1_000, 50, 150), 1_000);
+        assert_eq!(global.vested_amount(1_000, 50, 500), 1_000);
+        // Without a vesting duration rewards unlock at the cliff
+        let instant = GlobalPool::default();
+        assert_eq!(instant.vested_amount(1_000, 50, 50), 1_000);
+    }
+
+    #[test]
+    fn release_vested_frees_finished_tranches() {
+        let global = vesting_global();
+        let mut pool = UserPool::default();
+        pool.add_vesting(1_000, 0);
+        pool.add_vesting(500, 50);
+        // The second tranche is still before its cliff
+        assert_eq!(pool.release_vested(&global, 50), 500);
+        assert_eq!(pool.release_vested(&global, 100), 500 + 250);
+        assert_eq!(pool.release_vested(&global, 150), 250);
+        assert_eq!(pool.unreleased_vesting(), 0);
+        assert!(pool.vesting.iter().all(|tranche| tranche.amount == 0));
+    }
+
+    #[test]
+    fn full_vesting_table_merges_into_the_newest_tranche() {
+        let global = vesting_global();
+        let mut pool = UserPool::default();
+        for i in 0..MAX_VESTING_TRANCHES as i64 {
+            pool.add_vesting(100, i);
+        }
+        pool.add_vesting(100, 107);
+        let newest = pool.vesting.iter().max_by_key(|tranche| tranche.start_time).unwrap();
+        assert_eq!(newest.amount, 200);
+        assert_eq!(newest.start_time, 57);
+        assert_eq!(pool.unreleased_vesting(), 100 * (MAX_VESTING_TRANCHES as u64 + 1));
+        // Releasing never takes back what a later merge pushed out again
+        let released = pool.release_vested(&global, 60);
+        pool.add_vesting(100, 60);
+        assert_eq!(pool.release_vested(&global, 60), 0);
+        assert!(released > 0);
+    }
+
+    #[test]
+    fn early_penalty_scales_with_the_remaining_lock() {
+        let mut global = GlobalPool { early_penalty_bps: 5_000, total_accrued: 1_000, ..lock_global() };
+        let locked = lock_item(1);
+        // Half of the 5 day lock bonus at 100 - 10, pro-rated by the 5 days left
+        assert_eq!(global.apply_early_penalty(&locked, 5 * DAY), 225 / 2);
+        assert_eq!(global.total_penalties, 112);
+        assert_eq!(global.total_accrued, 888);
+        // No penalty from lock expiry on, nor outside lock tiers
+        assert_eq!(global.apply_early_penalty(&locked, 10 * DAY), 0);
+        assert_eq!(global.apply_early_penalty(&item(2, 2, 0, 0), DAY), 0);
+    }
+
+    #[test]
+    fn early_penalty_counts_only_the_running_lock_period() {
+        let mut global = GlobalPool { early_penalty_bps: 10_000, ..lock_global() };
+        // Relocked at day 10 for another 10 days, the first period does not count
+        let relocked = StakedNFT { lock_time: 20 * DAY, ..lock_item(1) };
+        assert_eq!(global.apply_early_penalty(&relocked, 12 * DAY), 180 * 8 / 10);
+    }
+
+    #[test]
+    fn redistributed_penalties_carry_the_remainder() {
+        let mut global = GlobalPool { early_penalty_flat: 100, redistribute_penalties: true, total_amount: 3, ..lock_global() };
+        global.apply_early_penalty(&lock_item(1), 0);
+        assert_eq!(global.penalty_index, 33);
+        assert_eq!(global.penalty_carry, 1);
+        global.early_penalty_flat = 2;
+        global.apply_early_penalty(&lock_item(1), 0);
+        assert_eq!(global.penalty_index, 34);
+        assert_eq!(global.penalty_carry, 0);
+    }
+
+    #[test]
+    fn claiming_before_an_early_unstake_does_not_avoid_the_penalty() {
+        let mut global = GlobalPool { early_unstake: true, early_penalty_bps: 10_000, total_accrued: 10_000, ..lock_global() };
+        let owner = Pubkey::new_unique();
+        let locked = lock_item(1);
+        let mut pool = UserPool { owner, ..Default::default() };
+        pool.add_nft(locked);
+
+        let claimed = pool.claim_reward(owner, locked.nft_addr, &global, 5 * DAY).unwrap();
+        assert_eq!(pool.settle_reward(&mut global, claimed, u64::MAX).unwrap(), 500);
+        let (reward, item) = pool.remove_nft(owner, locked.nft_addr, &global, 5 * DAY).unwrap();
+        assert_eq!(reward, 0);
+        pool.pending_reward += reward;
+        let penalty = global.apply_early_penalty(&item, 5 * DAY);
+        // The full lock bonus of 5 days at 90, pro-rated by the 5 days left
+        assert_eq!(penalty, 225);
+        pool.charge_penalty(penalty);
+        assert_eq!(pool.penalty_due, 225);
+
+        // Later rewards pay the penalty off first
+        assert_eq!(pool.settle_reward(&mut global, 200, u64::MAX).unwrap(), 0);
+        assert_eq!(pool.settle_reward(&mut global, 100, u64::MAX).unwrap(), 75);
+        assert_eq!(pool.penalty_due, 0);
+    }
+
+    #[test]
+    fn early_penalties_come_out_of_the_pending_reward_first() {
+        let mut pool = UserPool { pending_reward: 100, ..Default::default() };
+        pool.charge_penalty(60);
+        assert_eq!(pool.pending_reward, 40);
+        assert_eq!(pool.penalty_due, 0);
+        pool.charge_penalty(70);
+        assert_eq!(pool.pending_reward, 0);
+        assert_eq!(pool.penalty_due, 30);
+    }
+
+    #[test]
+    fn lock_rate_decays_at_lock_expiry() {
+        let mut global = lock_global();
+        let locked = lock_item(1);
+        assert_eq!(global.post_lock_rate(&locked, 0), Some(10));
+        assert_eq!(global.accrual(&locked, 100, true, 0, 10 * DAY), 1_000);
+        assert_eq!(global.accrual(&locked, 100, true, 10 * DAY, 12 * DAY), 20);
+        assert_eq!(global.accrual(&locked, 100, true, 9 * DAY, 11 * DAY), 110);
+
+        global.post_lock_mode = POST_LOCK_CUSTOM;
+        global.post_lock_rate = 50;
+        assert_eq!(global.accrual(&locked, 100, true, 9 * DAY, 11 * DAY), 150);
+
+        global.post_lock_mode = POST_LOCK_KEEP;
+        assert_eq!(global.post_lock_rate(&locked, 0), None);
+        assert_eq!(global.accrual(&locked, 100, true, 9 * DAY, 11 * DAY), 200);
+    }
+
+    #[test]
+    fn post_lock_rate_changes_apply_from_the_change_on() {
+        let mut global = lock_global();
+        let locked = lock_item(1);
+        global.set_post_lock(POST_LOCK_CUSTOM, 50, 12 * DAY);
+        // 2 days past lock_time at normal_rate, then 50 a day
+        assert_eq!(global.accrual(&locked, 100, true, 0, 14 * DAY), 1_000 + 20 + 100);
+        assert_eq!(global.post_lock_rate(&locked, 12 * DAY - 1), Some(10));
+        assert_eq!(global.post_lock_rate(&locked, 12 * DAY), Some(50));
+    }
+
+    #[test]
+    fn auto_relock_keeps_the_lock_rate() {
+        let global = lock_global();
+        let mut locked = lock_item(1);
+        locked.auto_relock = 1;
+        assert_eq!(global.post_lock_rate(&locked, 0), None);
+        assert_eq!(global.accrual(&locked, 100, true, 0, 20 * DAY), 2_000);
+    }
+
+    fn loyalty_global() -> GlobalPool {
+        GlobalPool {
+            loyalty_tiers: vec![
+                LoyaltyTier { min_staked: 5 * DAY, multiplier_bps: 15_000 },
+                LoyaltyTier { min_staked: 10 * DAY, multiplier_bps: 20_000 },
+            ],
+            exclude_paused_time: true,
+            ..lock_global()
+        }
+    }
+
+    #[test]
+    fn loyalty_tiers_apply_piecewise_from_each_boundary() {
+        let global = loyalty_global();
+        let staked = item(1, 2, 0, 0);
+        assert_eq!(global.accrual(&staked, 100, false, 0, 15 * DAY), 500 + 750 + 1_000);
+        assert_eq!(global.accrual(&staked, 100, false, 4 * DAY, 6 * DAY), 100 + 150);
+        assert_eq!(global.loyalty_multiplier(&staked, 5 * DAY - 1), BPS_DENOMINATOR);
+        assert_eq!(global.loyalty_multiplier(&staked, 5 * DAY), 15_000);
+    }
+
+    #[test]
+    fn loyalty_tiers_combine_with_lock_expiry() {
+        let global = loyalty_global();
+        let locked = StakedNFT { lock_time: 7 * DAY, ..lock_item(1) };
+        // 1 day at 100, 2 days at 150 then the post-lock rate of 10 boosted to 15
+        assert_eq!(global.accrual(&locked, 100, true, 4 * DAY, 8 * DAY), 100 + 300 + 15);
+    }
+
+    #[test]
+    fn loyalty_tier_changes_apply_from_the_change_on() {
+        let mut global = loyalty_global();
+        let staked = item(1, 2, 0, 0);
+        global.set_loyalty_tiers(vec![LoyaltyTier { min_staked: DAY, multiplier_bps: 30_000 }], 8 * DAY);
+        // 5 days at 100 and 3 at 150 under the old tiers, then 300 a day
+        assert_eq!(global.accrual(&staked, 100, false, 0, 10 * DAY), 500 + 450 + 600);
+        assert_eq!(global.loyalty_multiplier(&staked, 8 * DAY - 1), 15_000);
+        assert_eq!(global.loyalty_multiplier(&staked, 8 * DAY), 30_000);
+    }
+
+    #[test]
+    fn loyalty_tiers_skip_paused_time() {
+        let mut global = loyalty_global();
+        global.pause(PAUSE_CLAIM, 5 * DAY);
+        global.unpause(PAUSE_CLAIM, 6 * DAY);
+        // Staking time still counts toward the tiers while paused
+        assert_eq!(global.accrual(&item(1, 2, 0, 0), 100, false, 0, 7 * DAY), 500 + 150);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,342 @@
+use anchor_lang::prelude::*;
+use solana_program::program::invoke_signed;
+use solana_program::program_pack::Pack;
+use solana_program::system_instruction;
+use spl_token_2022::extension::{
+    transfer_fee::TransferFeeConfig, ExtensionType, StateWithExtensions,
+};
+use spl_token_2022::state::{Account as TokenAccount, Mint};
+
+use crate::constants::*;
+use crate::errors::*;
+
+// Helpers for reward token accounts that may belong to either spl-token or Token-2022
+
+pub fn is_token_program(key: &Pubkey) -> bool {
+    key.eq(&spl_token::ID) || key.eq(&spl_token_2022::ID)
+}
+
+pub fn unpack_token_account(info: &AccountInfo) -> Result<TokenAccount> {
+    require!(is_token_program(info.owner), StakingError::InvalidTokenAccount);
+    let data = info.try_borrow_data()?;
+    let account = StateWithExtensions::<TokenAccount>::unpack(&data)?;
+    Ok(account.base)
+}
+
+pub fn unpack_mint(info: &AccountInfo) -> Result<Mint> {
+    require!(is_token_program(info.owner), StakingError::InvalidTokenAccount);
+    let data = info.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    Ok(mint.base)
+}
+
+pub fn check_token_account(info: &AccountInfo, mint: &Pubkey, owner: &Pubkey) -> Result<()> {
+    let account = unpack_token_account(info)?;
+    require!(
+        account.mint.eq(mint) && account.owner.eq(owner),
+        StakingError::InvalidTokenAccount
+    );
+    Ok(())
+}
+
+pub fn token_amount(info: &AccountInfo) -> Result<u64> {
+    Ok(unpack_token_account(info)?.amount)
+}
+
+fn fee_config(mint: &AccountInfo) -> Result<Option<TransferFeeConfig>> {
+    if mint.owner.ne(&spl_token_2022::ID) {
+        return Ok(None);
+    }
+    let data = mint.try_borrow_data()?;
+    let mint = StateWithExtensions::<Mint>::unpack(&data)?;
+    Ok(mint.get_extension::<TransferFeeConfig>().ok().copied())
+}
+
+// Fee withheld by the mint when amount is transferred, 0 without a transfer fee extension
+pub fn transfer_fee(mint: &AccountInfo, amount: u64) -> Result<u64> {
+    match fee_config(mint)? {
+        Some(config) => epoch_fee(&config, Clock::get()?.epoch, amount),
+        None => Ok(0),
+    }
+}
+
+// Smallest amount that delivers at least net after the transfer fee
+pub fn gross_up(mint: &AccountInfo, net: u64) -> Result<u64> {
+    match fee_config(mint)? {
+        Some(config) => gross_up_amount(&config, Clock::get()?.epoch, net),
+        None => Ok(net),
+    }
+}
+
+pub fn epoch_fee(config: &TransferFeeConfig, epoch: u64, amount: u64) -> Result<u64> {
+    Ok(config
+        .calculate_epoch_fee(epoch, amount)
+        .ok_or(StakingError::InvalidTokenAccount)?)
+}
+
+// ceil(net * 10000 / (10000 - bps)), or net plus maximum_fee once the fee is capped
+pub fn gross_up_amount(config: &TransferFeeConfig, epoch: u64, net: u64) -> Result<u64> {
+    if net == 0 {
+        return Ok(0);
+    }
+    let fee = config.get_epoch_fee(epoch);
+    let bps = u16::from(fee.transfer_fee_basis_points) as u128;
+    let one = BPS_DENOMINATOR as u128;
+    let capped = net as u128 + u64::from(fee.maximum_fee) as u128;
+    let gross = if bps < one {
+        let unit = one - bps;
+        (net as u128 * one).div_ceil(unit).min(capped)
+    } else {
+        capped
+    };
+    let mut gross = u64::try_from(gross).map_err(|_| StakingError::InvalidTokenAccount)?;
+    if gross - epoch_fee(config, epoch, gross)? < net {
+        gross = gross.checked_add(1).ok_or(StakingError::InvalidTokenAccount)?;
+    }
+    Ok(gross)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_checked<'info>(
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let decimals = unpack_mint(mint)?.decimals;
+    invoke_signed(
+        &spl_token_2022::instruction::transfer_checked(
+            token_program.key,
+            from.key,
+            mint.key,
+            to.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[from.clone(), mint.clone(), to.clone(), authority.clone(), token_program.clone()],
+        signer_seeds,
+    )?;
+    Ok(())
+}
+
+pub fn mint_to<'info>(
+    token_program: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    to: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    invoke_signed(
+        &spl_token_2022::instruction::mint_to(
+            token_program.key,
+            mint.key,
+            to.key,
+            authority.key,
+            &[],
+            amount,
+        )?,
+        &[mint.clone(), to.clone(), authority.clone(), token_program.clone()],
+        signer_seeds,
+    )?;
+    Ok(())
+}
+
+pub fn burn<'info>(
+    token_program: &AccountInfo<'info>,
+    from: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    authority: &AccountInfo<'info>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let decimals = unpack_mint(mint)?.decimals;
+    invoke_signed(
+        &spl_token_2022::instruction::burn_checked(
+            token_program.key,
+            from.key,
+            mint.key,
+            authority.key,
+            &[],
+            amount,
+            decimals,
+        )?,
+        &[from.clone(), mint.clone(), authority.clone(), token_program.clone()],
+        signer_seeds,
+    )?;
+    Ok(())
+}
+
+// Creates a program owned token account at a PDA unless it already exists
+#[allow(clippy::too_many_arguments)]
+pub fn create_token_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    mint: &AccountInfo<'info>,
+    owner: &Pubkey,
+    token_program: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    account_seeds: &[&[u8]],
+) -> Result<()> {
+    if !account.data_is_empty() {
+        return Ok(());
+    }
+    let space = if token_program.key.eq(&spl_token_2022::ID) {
+        let data = mint.try_borrow_data()?;
+        let mint_state = StateWithExtensions::<Mint>::unpack(&data)?;
+        let mint_extensions = mint_state.get_extension_types()?;
+        let account_extensions = ExtensionType::get_required_init_account_extensions(&mint_extensions);
+        ExtensionType::get_account_len::<TokenAccount>(&account_extensions)
+    } else {
+        spl_token::state::Account::LEN
+    };
+    let lamports = Rent::get()?.minimum_balance(space);
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            account.key,
+            lamports,
+            space as u64,
+            token_program.key,
+        ),
+        &[payer.clone(), account.clone(), system_program.clone()],
+        &[account_seeds],
+    )?;
+    invoke_signed(
+        &spl_token_2022::instruction::initialize_account3(
+            token_program.key,
+            account.key,
+            mint.key,
+            owner,
+        )?,
+        &[account.clone(), mint.clone(), token_program.clone()],
+        &[],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spl_token_2022::extension::transfer_fee::TransferFee;
+    use spl_token_2022::state::AccountState;
+
+    fn token_account_data(mint: Pubkey, owner: Pubkey, amount: u64) -> Vec<u8> {
+        let account = TokenAccount { mint, owner, amount, state: AccountState::Initialized, ..Default::default() };
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount::pack(account, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn both_token_programs_are_accepted() {
+        assert!(is_token_program(&spl_token::ID));
+        assert!(is_token_program(&spl_token_2022::ID));
+        assert!(!is_token_program(&solana_program::system_program::ID));
+    }
+
+    #[test]
+    fn token_accounts_are_checked_against_mint_and_owner() {
+        let key = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = token_account_data(mint, owner, 500);
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &spl_token::ID, false, 0);
+        assert_eq!(token_amount(&info).unwrap(), 500);
+        assert!(check_token_account(&info, &mint, &owner).is_ok());
+        assert!(check_token_account(&info, &mint, &key).is_err());
+        assert!(check_token_account(&info, &key, &owner).is_err());
+    }
+
+    #[test]
+    fn accounts_of_other_programs_are_rejected() {
+        let key = Pubkey::new_unique();
+        let mut lamports = 0;
+        let mut data = token_account_data(Pubkey::new_unique(), Pubkey::new_unique(), 500);
+        let owner = solana_program::system_program::ID;
+        let info = AccountInfo::new(&key, false, false, &mut lamports, &mut data, &owner, false, 0);
+        assert!(unpack_token_account(&info).is_err());
+    }
+
+    fn config(old_bps: u16, new_bps: u16, new_epoch: u64, maximum_fee: u64) -> TransferFeeConfig {
+        TransferFeeConfig {
+            older_transfer_fee: TransferFee {
+                epoch: 0.into(),
+                maximum_fee: maximum_fee.into(),
+                transfer_fee_basis_points: old_bps.into(),
+            },
+            newer_transfer_fee: TransferFee {
+                epoch: new_epoch.into(),
+                maximum_fee: maximum_fee.into(),
+                transfer_fee_basis_points: new_bps.into(),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn epoch_fee_rounds_up_and_caps() {
+        let config = config(100, 100, 0, 5);
+        assert_eq!(epoch_fee(&config, 0, 0).unwrap(), 0);
+        assert_eq!(epoch_fee(&config, 0, 1).unwrap(), 1);
+        assert_eq!(epoch_fee(&config, 0, 250).unwrap(), 3);
+        assert_eq!(epoch_fee(&config, 0, 1_000_000).unwrap(), 5);
+    }
+
+    #[test]
+    fn epoch_fee_follows_the_scheduled_fee() {
+        let config = config(100, 500, 10, u64::MAX);
+        assert_eq!(epoch_fee(&config, 9, 1_000).unwrap(), 10);
+        assert_eq!(epoch_fee(&config, 10, 1_000).unwrap(), 50);
+    }
+
+    #[test]
+    fn gross_up_delivers_at_least_net() {
+        let config = config(100, 100, 0, u64::MAX);
+        for net in [0, 1, 99, 100, 101, 9_999, 1_000_000] {
+            let gross = gross_up_amount(&config, 0, net).unwrap();
+            assert!(gross - epoch_fee(&config, 0, gross).unwrap() >= net);
+            if gross > 0 {
+                let smaller = gross - 1;
+                assert!(smaller - epoch_fee(&config, 0, smaller).unwrap() < net);
+            }
+        }
+        assert_eq!(gross_up_amount(&config, 0, 990).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn gross_up_with_capped_fee_adds_the_cap() {
+        let config = config(1_000, 1_000, 0, 7);
+        assert_eq!(gross_up_amount(&config, 0, 1_000_000).unwrap(), 1_000_007);
+    }
+
+    #[test]
+    fn gross_up_handles_large_amounts() {
+        let config = config(250, 250, 0, u64::MAX);
+        for net in [123_456_789_012, u64::MAX / 2] {
+            let gross = gross_up_amount(&config, 0, net).unwrap();
+            assert!(gross - epoch_fee(&config, 0, gross).unwrap() >= net);
+            assert!(gross - 1 - epoch_fee(&config, 0, gross - 1).unwrap() < net);
+        }
+        assert!(gross_up_amount(&config, 0, u64::MAX).is_err());
+    }
+
+    #[test]
+    fn gross_up_switches_to_the_cap_where_the_fee_hits_it() {
+        let capped = config(100, 100, 0, 50);
+        // 1% until the fee reaches the 50 token cap at 5_000
+        assert_eq!(gross_up_amount(&capped, 0, 4_900).unwrap(), 4_950);
+        assert_eq!(gross_up_amount(&capped, 0, 4_950).unwrap(), 5_000);
+        assert_eq!(gross_up_amount(&capped, 0, 4_951).unwrap(), 5_001);
+        assert_eq!(gross_up_amount(&capped, 0, u64::MAX - 50).unwrap(), u64::MAX);
+        // A 100% fee can only be covered by the cap
+        let full = config(10_000, 10_000, 0, 9);
+        assert_eq!(gross_up_amount(&full, 0, 1_000).unwrap(), 1_009);
+    }
+}